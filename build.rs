@@ -0,0 +1,44 @@
+// Records the git commit being built, for --version.  Only a checkout of this crate itself has a commit to record; a
+// copy of it built inside another project's checkout, or from a registry, is "unknown".
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn main()
+{
+    let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let git_dir = manifest_dir.join(".git");
+    if git_dir.exists() {
+        println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+        println!("cargo:rerun-if-changed={}", git_dir.join("refs").display());
+    }
+
+    let commit = if is_toplevel(&manifest_dir) { git(&manifest_dir, &["rev-parse", "HEAD"]) } else { None };
+
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit.unwrap_or_else(|| "unknown".to_string()));
+}
+
+fn is_toplevel(dir : &Path) -> bool
+{
+    match (git(dir, &["rev-parse", "--show-toplevel"]), dir.canonicalize()) {
+        (Some(toplevel), Ok(dir)) => Path::new(&toplevel).canonicalize().is_ok_and(|toplevel| toplevel == dir),
+        _ => false
+    }
+}
+
+fn git(
+    dir : &Path,
+    args : &[&str]
+) -> Option<String>
+{
+    Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+}
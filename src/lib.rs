@@ -0,0 +1,1172 @@
+// Predicts the leader schedule of the next epoch from the stake currently delegated on a cluster, as the bank will
+// compute it at the epoch boundary.  The solana_leader_schedule binary is a command line interface over this library.
+//
+// The computation itself (decoding stake accounts and computing a schedule from stakes) needs no RPC client;
+// LeaderSchedulePredictor, which fetches what the computation needs, is only built with the rpc feature.
+use solana_ledger::leader_schedule::LeaderSchedule;
+use solana_sdk::clock::NUM_CONSECUTIVE_LEADER_SLOTS;
+use solana_sdk::epoch_schedule::EpochSchedule;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::stake::state::{Delegation, Lockup};
+use solana_sdk::stake_history::StakeHistory;
+use std::ops::RangeInclusive;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "rpc")]
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+#[cfg(feature = "rpc")]
+use solana_client::client_error::ClientError;
+#[cfg(feature = "rpc")]
+use solana_client::rpc_client::RpcClient;
+#[cfg(feature = "rpc")]
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+#[cfg(feature = "rpc")]
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+#[cfg(feature = "rpc")]
+use solana_sdk::account::Account;
+#[cfg(feature = "rpc")]
+use solana_sdk::commitment_config::CommitmentConfig;
+#[cfg(feature = "rpc")]
+use solana_sdk::epoch_info::EpochInfo;
+#[cfg(feature = "rpc")]
+use solana_sdk::feature_set::reduce_stake_warmup_cooldown;
+#[cfg(feature = "rpc")]
+use solana_sdk::stake_history::StakeHistoryEntry;
+
+// Every map in this crate is keyed by pubkeys, which are already uniformly distributed, so the DoS resistance of the
+// default SipHash buys nothing and aHash is used for its speed instead
+pub type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
+
+// The stake of each of a set of vote accounts, and the identity of each
+pub type DelegatedStakes = (HashMap<Pubkey, u128>, HashMap<Pubkey, Pubkey>);
+
+// Offsets of the only fields of a bincode-encoded StakeState that are needed.  The enum tag is a u32, followed by
+// Meta (rent_exempt_reserve u64, staker, withdrawer, lockup of unix_timestamp i64, epoch u64, custodian) and then, for
+// the Stake variant, Delegation (voter_pubkey, stake, activation_epoch, deactivation_epoch, warmup_cooldown_rate
+// f64, ...).
+const STAKE_STATE_TAG_STAKE : u32 = 2;
+const STAKE_STATE_WITHDRAWER_OFFSET : usize = 4 + 8 + 32;
+const STAKE_STATE_LOCKUP_OFFSET : usize = STAKE_STATE_WITHDRAWER_OFFSET + 32;
+const STAKE_STATE_VOTER_PUBKEY_OFFSET : usize = STAKE_STATE_LOCKUP_OFFSET + 8 + 8 + 32;
+const STAKE_STATE_STAKE_OFFSET : usize = STAKE_STATE_VOTER_PUBKEY_OFFSET + 32;
+const STAKE_STATE_ACTIVATION_EPOCH_OFFSET : usize = STAKE_STATE_STAKE_OFFSET + 8;
+const STAKE_STATE_DEACTIVATION_EPOCH_OFFSET : usize = STAKE_STATE_ACTIVATION_EPOCH_OFFSET + 8;
+const STAKE_STATE_WARMUP_COOLDOWN_RATE_OFFSET : usize = STAKE_STATE_DEACTIVATION_EPOCH_OFFSET + 8;
+const STAKE_STATE_MIN_LEN : usize = STAKE_STATE_WARMUP_COOLDOWN_RATE_OFFSET + 8;
+
+// Fetches stakes from an RPC endpoint and computes the leader schedule they produce
+#[cfg(feature = "rpc")]
+pub struct LeaderSchedulePredictor
+{
+    rpc_client : RpcClient
+}
+
+// The stake that determines the leader schedule of epoch, which is slots_in_epoch slots long starting at first_slot
+pub struct Stakes
+{
+    pub epoch : u64,
+
+    pub first_slot : u64,
+
+    pub slots_in_epoch : u64,
+
+    // Effective stake per vote account; vote accounts with none are absent
+    pub vote_stakes : HashMap<Pubkey, u128>,
+
+    // Validator identity of each vote account in vote_stakes that exists and is owned by the vote program
+    pub identities : HashMap<Pubkey, Pubkey>,
+
+    // Effective stake per validator identity, which is what the schedule is computed from
+    pub identity_stakes : HashMap<Pubkey, u128>
+}
+
+pub struct PredictedSchedule
+{
+    pub epoch : u64,
+
+    pub first_slot : u64,
+
+    pub leader_schedule : LeaderSchedule
+}
+
+// The leader of each slot, as known to whatever implements it, for network simulators and test harnesses that model
+// leader behavior without caring where the schedule came from
+pub trait LeaderOracle
+{
+    // The leader of slot, or None if it is not known
+    fn leader_at(
+        &self,
+        slot : u64
+    ) -> Option<Pubkey>;
+
+    // The first slot after slot whose leader is not slot's leader, and that leader, or None if not known
+    fn next_leader_change(
+        &self,
+        slot : u64
+    ) -> Option<(u64, Pubkey)>;
+}
+
+// Only the fields of a stake account's Delegation (and Meta) that the schedule computation and reports use
+#[derive(Clone)]
+pub struct StakeDelegation
+{
+    pub withdrawer : Pubkey,
+
+    pub lockup : Lockup,
+
+    pub voter_pubkey : Pubkey,
+
+    pub stake : u64,
+
+    pub activation_epoch : u64,
+
+    pub deactivation_epoch : u64,
+
+    pub warmup_cooldown_rate : f64
+}
+
+#[derive(Debug)]
+pub enum Error
+{
+    Rpc(String),
+
+    Decode { pubkey : Pubkey, message : String },
+
+    StakeOverflow { pubkey : Pubkey, stake : u128 },
+
+    // Many public and commercial RPC providers disable getProgramAccounts, or serve it only for programs in their
+    // secondary index, which stake usually is not
+    GetProgramAccountsUnavailable { url : String, message : String },
+
+    // Current stakes determine the schedule of the next epoch and no other
+    EpochNotPredictable { epoch : u64, next_epoch : u64 },
+
+    // A schedule can only be computed if at least one validator has stake
+    NoStake
+}
+
+impl std::fmt::Display for Error
+{
+    fn fmt(
+        &self,
+        f : &mut std::fmt::Formatter
+    ) -> std::fmt::Result
+    {
+        match self {
+            Error::Rpc(message) => write!(f, "{}", message),
+            Error::Decode { pubkey, message } => write!(f, "Failed to decode stake account {}: {}", pubkey, message),
+            Error::StakeOverflow { pubkey, stake } => write!(f, "Total stake {} of {} exceeds u64", stake, pubkey),
+            Error::GetProgramAccountsUnavailable { url, message } => write!(
+                f,
+                "The RPC endpoint {} does not serve getProgramAccounts for the stake program: {}",
+                url, message
+            ),
+            Error::EpochNotPredictable { epoch, next_epoch } => write!(
+                f,
+                "The schedule of epoch {} cannot be predicted; current stakes determine only that of epoch {}",
+                epoch, next_epoch
+            ),
+            Error::NoStake => write!(f, "No validator has any stake to compute a schedule from")
+        }
+    }
+}
+
+impl std::error::Error for Error
+{
+}
+
+#[cfg(feature = "rpc")]
+impl LeaderSchedulePredictor
+{
+    // Uses finalized commitment, so that the stakes read are those the cluster cannot roll back
+    pub fn new(rpc_url : String) -> Self
+    {
+        Self::from_rpc_client(RpcClient::new_with_commitment(rpc_url, CommitmentConfig::finalized()))
+    }
+
+    // For callers that need control over the client's transport or commitment
+    pub fn from_rpc_client(rpc_client : RpcClient) -> Self
+    {
+        LeaderSchedulePredictor { rpc_client }
+    }
+
+    pub fn rpc_client(&self) -> &RpcClient
+    {
+        &self.rpc_client
+    }
+
+    pub fn fetch_epoch_info(&self) -> Result<EpochInfo, Error>
+    {
+        self.rpc_client.get_epoch_info().map_err(|e| Error::Rpc(format!("Failed to fetch epoch info: {}", e)))
+    }
+
+    // The cluster's epoch lengths, which differ from the mainnet length of 432000 slots on clusters with warmup
+    // epochs and on custom clusters
+    pub fn fetch_epoch_schedule(&self) -> Result<EpochSchedule, Error>
+    {
+        self.rpc_client.get_epoch_schedule().map_err(|e| Error::Rpc(format!("Failed to fetch epoch schedule: {}", e)))
+    }
+
+    // Every delegated stake account, each holding only the leading bytes that decode_delegation reads.  Accounts in any
+    // other state contribute no stake, so the RPC node is asked to leave them out of the response.
+    pub fn fetch_stake_accounts(&self) -> Result<Vec<(Pubkey, Account)>, Error>
+    {
+        self.fetch_delegated_stake_accounts(vec![])
+    }
+
+    // The delegated stake accounts that also match filters
+    fn fetch_delegated_stake_accounts(
+        &self,
+        mut filters : Vec<RpcFilterType>
+    ) -> Result<Vec<(Pubkey, Account)>, Error>
+    {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, STAKE_STATE_TAG_STAKE.to_le_bytes().to_vec())));
+
+        self.rpc_client
+            .get_program_accounts_with_config(&solana_sdk::stake::program::id(), RpcProgramAccountsConfig {
+                filters : Some(filters),
+                account_config : stake_account_config(self.rpc_client.commitment()),
+                ..RpcProgramAccountsConfig::default()
+            })
+            .map_err(|e| stake_accounts_error(self.rpc_client.url(), e))
+    }
+
+    // Fetches every stake account, along with the epoch info of the epoch that they are as of.  Should an epoch
+    // boundary pass during the lengthy fetch, the accounts would be a mix of the state of both epochs, so the fetch is
+    // repeated until the epoch after it is the one before it.  epoch_info is that fetched before the first attempt.
+    pub fn fetch_stake_accounts_in_epoch(
+        &self,
+        mut epoch_info : EpochInfo
+    ) -> Result<(EpochInfo, Vec<(Pubkey, Account)>), Error>
+    {
+        loop {
+            let accounts = self.fetch_stake_accounts()?;
+            let after = self.fetch_epoch_info()?;
+            if after.epoch == epoch_info.epoch {
+                return Ok((after, accounts));
+            }
+            epoch_info = after;
+        }
+    }
+
+    // Calls each RPC method that prediction requires, each in a way that returns little or nothing, so that an endpoint
+    // which won't serve one is found out before the lengthy stake fetch.  Returns each method with its outcome.
+    pub fn probe(&self) -> Vec<(&'static str, Result<(), Error>)>
+    {
+        let rpc_error = |what : &str, e : ClientError| Error::Rpc(format!("Failed to fetch {}: {}", what, e));
+
+        // No stake account is 1 byte long, so this matches nothing, but is refused just as the full fetch would be by
+        // endpoints that don't serve getProgramAccounts for the stake program
+        let get_program_accounts = self
+            .rpc_client
+            .get_program_accounts_with_config(&solana_sdk::stake::program::id(), RpcProgramAccountsConfig {
+                filters : Some(vec![RpcFilterType::DataSize(1)]),
+                account_config : stake_account_config(self.rpc_client.commitment()),
+                ..RpcProgramAccountsConfig::default()
+            })
+            .map(|_| ())
+            .map_err(|e| stake_accounts_error(self.rpc_client.url(), e));
+
+        vec![
+            ("getEpochInfo", self.fetch_epoch_info().map(|_| ())),
+            ("getEpochSchedule", self.fetch_epoch_schedule().map(|_| ())),
+            ("getProgramAccounts", get_program_accounts),
+            (
+                "getAccountInfo",
+                self.rpc_client
+                    .get_account(&solana_sdk::sysvar::stake_history::id())
+                    .map(|_| ())
+                    .map_err(|e| rpc_error("stake history", e))
+            ),
+            (
+                "getVoteAccounts",
+                self.rpc_client.get_vote_accounts().map(|_| ()).map_err(|e| rpc_error("vote accounts", e))
+            ),
+            (
+                "getMultipleAccounts",
+                self.rpc_client
+                    .get_multiple_accounts(&[solana_sdk::sysvar::stake_history::id()])
+                    .map(|_| ())
+                    .map_err(|e| rpc_error("multiple accounts", e))
+            )
+        ]
+    }
+
+    // Fetches the stakes that will determine the next epoch's schedule
+    pub fn fetch_stakes(&self) -> Result<Stakes, Error>
+    {
+        let (epoch_info, accounts) = self.fetch_stake_accounts_in_epoch(self.fetch_epoch_info()?)?;
+
+        self.aggregate_stakes(&epoch_info, accounts, false, |_, _| ())
+    }
+
+    pub fn predict(
+        &self,
+        epoch : u64
+    ) -> Result<PredictedSchedule, Error>
+    {
+        // Checked before the lengthy stake fetch, which would be wasted on an epoch that can't be predicted
+        let epoch_info = self.fetch_epoch_info()?;
+
+        if epoch != (epoch_info.epoch + 1) {
+            return Err(Error::EpochNotPredictable { epoch, next_epoch : epoch_info.epoch + 1 });
+        }
+
+        let (epoch_info, accounts) = self.fetch_stake_accounts_in_epoch(epoch_info)?;
+
+        // The epoch may have advanced during the fetch
+        if epoch != (epoch_info.epoch + 1) {
+            return Err(Error::EpochNotPredictable { epoch, next_epoch : epoch_info.epoch + 1 });
+        }
+
+        self.aggregate_stakes(&epoch_info, accounts, false, |_, _| ())?.predict()
+    }
+
+    // Totals the effective stake of the given stake accounts, as of epoch_info's epoch, per vote account and per
+    // validator identity.  visit is called with each stake account that contributes stake, with the delegation's stake
+    // already reduced to the amount counted.
+    pub fn aggregate_stakes(
+        &self,
+        epoch_info : &EpochInfo,
+        accounts : Vec<(Pubkey, Account)>,
+        include_current_epoch_activations : bool,
+        mut visit : impl FnMut(&Pubkey, &StakeDelegation)
+    ) -> Result<Stakes, Error>
+    {
+        let current_epoch = epoch_info.epoch;
+
+        let epoch_schedule = self.fetch_epoch_schedule()?;
+
+        let stake_history = self.fetch_stake_history()?;
+
+        let new_rate_activation_epoch = self.fetch_new_rate_activation_epoch(&epoch_schedule)?;
+
+        // Totals are accumulated in u128 so that no amount of stake can overflow the sum; they are narrowed back to
+        // the u64 the leader schedule algorithm uses only once aggregation is complete
+        let mut vote_stakes = HashMap::<Pubkey, u128>::default();
+
+        for (pubkey, account) in accounts {
+            // Zero-length accounts owned by the stake program are system accounts that were re-assigned and are to be
+            // ignored
+            if account.data.is_empty() {
+                continue;
+            }
+
+            if let Some(mut delegation) =
+                decode_delegation(&account.data).map_err(|message| Error::Decode { pubkey, message })?
+            {
+                // The bank computes the next epoch's leader schedule from each delegation's effective stake as of the
+                // first slot of this epoch, which applies warmup and cooldown according to the stake history.  Stake
+                // activated in this epoch is not yet effective at all.
+                //
+                // include_current_epoch_activations diverges from this by counting such stake in full, which models
+                // the schedule as if activation were immediate; the result is not what the cluster will use.
+                if !include_current_epoch_activations || (delegation.activation_epoch != current_epoch) {
+                    delegation.stake =
+                        delegation.effective_stake(current_epoch, &stake_history, new_rate_activation_epoch);
+                }
+                // Vote accounts with no effective stake are left out of the schedule entirely, as the bank does
+                if delegation.stake == 0 {
+                    continue;
+                }
+                // Add the stake in this stake account to the total for the delegated-to vote account
+                *(vote_stakes.entry(delegation.voter_pubkey).or_insert(0)) += delegation.stake as u128;
+                visit(&pubkey, &delegation);
+            }
+        }
+
+        let identities = vote_identities(&self.rpc_client, vote_stakes.keys().copied().collect())?;
+
+        Ok(stakes(&epoch_schedule, current_epoch + 1, vote_stakes, identities))
+    }
+
+    // Projects the stakes of later epochs from the stake accounts as they are now, on the assumption that no
+    // delegation changes from here on.  Warmup and cooldown are applied epoch by epoch as the bank applies them, with
+    // the stake history of each epoch to come built as the bank builds it, from the activating, effective and
+    // deactivating stake of every delegation.  Returns the Stakes of each of epochs, all of which must be after
+    // epoch_info's epoch.
+    pub fn project_stakes(
+        &self,
+        epoch_info : &EpochInfo,
+        accounts : Vec<(Pubkey, Account)>,
+        epochs : RangeInclusive<u64>
+    ) -> Result<Vec<Stakes>, Error>
+    {
+        let mut stake_history = self.fetch_stake_history()?;
+
+        let epoch_schedule = self.fetch_epoch_schedule()?;
+
+        let new_rate_activation_epoch = self.fetch_new_rate_activation_epoch(&epoch_schedule)?;
+
+        let mut delegations = vec![];
+
+        for (pubkey, account) in accounts {
+            if account.data.is_empty() {
+                continue;
+            }
+            if let Some(delegation) =
+                decode_delegation(&account.data).map_err(|message| Error::Decode { pubkey, message })?
+            {
+                delegations.push(delegation.delegation());
+            }
+        }
+
+        let mut voters = delegations.iter().map(|delegation| delegation.voter_pubkey).collect::<Vec<_>>();
+        voters.sort_unstable();
+        voters.dedup();
+
+        let identities = vote_identities(&self.rpc_client, voters)?;
+
+        let mut projected = vec![];
+
+        // The schedule of each epoch is computed from the effective stake of the epoch before it
+        for epoch in epoch_info.epoch..*epochs.end() {
+            if epochs.contains(&(epoch + 1)) {
+                let mut vote_stakes = HashMap::<Pubkey, u128>::default();
+                for delegation in &delegations {
+                    let stake = delegation.stake(epoch, Some(&stake_history), new_rate_activation_epoch);
+                    if stake > 0 {
+                        *(vote_stakes.entry(delegation.voter_pubkey).or_insert(0)) += stake as u128;
+                    }
+                }
+                let vote_identities = identities
+                    .iter()
+                    .filter(|(vote_pubkey, _)| vote_stakes.contains_key(vote_pubkey))
+                    .map(|(vote_pubkey, identity)| (*vote_pubkey, *identity))
+                    .collect();
+                projected.push(stakes(&epoch_schedule, epoch + 1, vote_stakes, vote_identities));
+            }
+
+            let mut entry = StakeHistoryEntry::default();
+            for delegation in &delegations {
+                let status = delegation.stake_activating_and_deactivating(
+                    epoch,
+                    Some(&stake_history),
+                    new_rate_activation_epoch
+                );
+                entry.effective += status.effective;
+                entry.activating += status.activating;
+                entry.deactivating += status.deactivating;
+            }
+            stake_history.add(epoch, entry);
+        }
+
+        Ok(projected)
+    }
+
+    // Reconstructs the stakes that the current epoch's schedule was computed from: the effective stake of each
+    // delegation as of the first slot of the previous epoch.  Only the stake accounts and vote account identities as
+    // they are now are available, so the result differs from what the bank used wherever a stake account has since
+    // been closed or a vote account's identity changed.
+    pub fn reconstruct_stakes(
+        &self,
+        epoch_info : &EpochInfo,
+        accounts : &[(Pubkey, Account)]
+    ) -> Result<Stakes, Error>
+    {
+        // Epoch 0 has no previous epoch; its schedule, like that of epoch 1, is computed from the genesis stakes
+        let stakes_epoch = epoch_info.epoch.saturating_sub(1);
+
+        let stake_history = self.fetch_stake_history()?;
+
+        let new_rate_activation_epoch = self.fetch_new_rate_activation_epoch(&self.fetch_epoch_schedule()?)?;
+
+        let mut vote_stakes = HashMap::<Pubkey, u128>::default();
+
+        for (pubkey, account) in accounts {
+            if account.data.is_empty() {
+                continue;
+            }
+            if let Some(delegation) =
+                decode_delegation(&account.data).map_err(|message| Error::Decode { pubkey : *pubkey, message })?
+            {
+                let stake = delegation.effective_stake(stakes_epoch, &stake_history, new_rate_activation_epoch);
+                if stake > 0 {
+                    *(vote_stakes.entry(delegation.voter_pubkey).or_insert(0)) += stake as u128;
+                }
+            }
+        }
+
+        let identities = vote_identities(&self.rpc_client, vote_stakes.keys().copied().collect())?;
+
+        Ok(stakes(&self.fetch_epoch_schedule()?, epoch_info.epoch, vote_stakes, identities))
+    }
+
+    // The leader of each slot of the current epoch as the RPC node's bank has it, from getLeaderSchedule
+    pub fn fetch_leader_schedule(
+        &self,
+        epoch_info : &EpochInfo
+    ) -> Result<Vec<Pubkey>, Error>
+    {
+        let schedule = self
+            .rpc_client
+            .get_leader_schedule(Some(epoch_info.absolute_slot))
+            .map_err(|e| Error::Rpc(format!("Failed to fetch leader schedule: {}", e)))?
+            .ok_or_else(|| Error::Rpc(format!("No leader schedule for epoch {}", epoch_info.epoch)))?;
+
+        let mut leaders = vec![None; epoch_info.slots_in_epoch as usize];
+
+        for (identity, slot_indexes) in schedule {
+            let identity = identity
+                .parse::<Pubkey>()
+                .map_err(|_| Error::Rpc(format!("Invalid pubkey {} in leader schedule", identity)))?;
+            for slot_index in slot_indexes {
+                *(leaders.get_mut(slot_index).ok_or_else(|| {
+                    Error::Rpc(format!("Slot index {} in leader schedule is beyond the epoch", slot_index))
+                })?) = Some(identity);
+            }
+        }
+
+        leaders
+            .into_iter()
+            .enumerate()
+            .map(|(slot_index, leader)| {
+                leader.ok_or_else(|| Error::Rpc(format!("No leader for slot index {} in leader schedule", slot_index)))
+            })
+            .collect()
+    }
+
+    fn fetch_stake_history(&self) -> Result<StakeHistory, Error>
+    {
+        self.rpc_client
+            .get_account(&solana_sdk::sysvar::stake_history::id())
+            .ok()
+            .and_then(|account| solana_sdk::account::from_account::<StakeHistory, _>(&account))
+            .ok_or_else(|| Error::Rpc("Failed to fetch stake history".to_string()))
+    }
+
+    // The epoch from which the bank warms up and cools down stake at the lower rate that the
+    // reduce_stake_warmup_cooldown feature introduced, found as the bank finds it; None until the feature is activated
+    fn fetch_new_rate_activation_epoch(
+        &self,
+        epoch_schedule : &EpochSchedule
+    ) -> Result<Option<u64>, Error>
+    {
+        self.rpc_client
+            .get_account_with_commitment(&reduce_stake_warmup_cooldown::id(), self.rpc_client.commitment())
+            .map(|response| {
+                response
+                    .value
+                    .and_then(|account| solana_sdk::feature::from_account(&account))
+                    .and_then(|feature| feature.activated_at)
+                    .map(|slot| epoch_schedule.get_epoch(slot))
+            })
+            .map_err(|e| Error::Rpc(format!("Failed to fetch the warmup and cooldown rate feature: {}", e)))
+    }
+
+    // Takes the stake of each vote account from getVoteAccounts rather than totaling every stake account, which
+    // avoids the getProgramAccounts call that is slow, large, and refused by many RPC providers.  activated_stake is
+    // the effective stake that the RPC node's bank has totaled for the vote account, which should match the full
+    // scan, but the totals must be taken on trust, and the stake of vote accounts whose state the node can't parse is
+    // missing.  No detail of individual stake accounts is available.
+    pub fn fetch_vote_account_stakes(
+        &self,
+        epoch_info : &EpochInfo
+    ) -> Result<Stakes, Error>
+    {
+        let vote_accounts = self
+            .rpc_client
+            .get_vote_accounts()
+            .map_err(|e| Error::Rpc(format!("Failed to fetch vote accounts: {}", e)))?;
+
+        let mut vote_stakes = HashMap::<Pubkey, u128>::default();
+
+        let mut identities = HashMap::<Pubkey, Pubkey>::default();
+
+        for vote_account in vote_accounts.current.iter().chain(vote_accounts.delinquent.iter()) {
+            if vote_account.activated_stake == 0 {
+                continue;
+            }
+            let pubkey = |s : &str| {
+                s.parse::<Pubkey>().map_err(|_| Error::Rpc(format!("Invalid pubkey {} in vote accounts", s)))
+            };
+            let vote_pubkey = pubkey(&vote_account.vote_pubkey)?;
+            vote_stakes.insert(vote_pubkey, vote_account.activated_stake as u128);
+            identities.insert(vote_pubkey, pubkey(&vote_account.node_pubkey)?);
+        }
+
+        Ok(stakes(&self.fetch_epoch_schedule()?, epoch_info.epoch + 1, vote_stakes, identities))
+    }
+
+    // As fetch_vote_account_stakes, except that the stake of the tracked validators, given by identity or by vote
+    // account, is totaled from their own stake accounts.  Those are fetched with a filter on the vote account they
+    // are delegated to, so the validators of interest have exactly the stake that the full scan would give them,
+    // while the stake fetched is a tiny fraction of it.  The stake of every other validator is still taken on trust.
+    pub fn fetch_tracked_stakes(
+        &self,
+        epoch_info : &EpochInfo,
+        tracked_identities : &[Pubkey],
+        tracked_vote_accounts : &[Pubkey]
+    ) -> Result<Stakes, Error>
+    {
+        let Stakes { epoch, first_slot, slots_in_epoch, mut vote_stakes, mut identities, .. } =
+            self.fetch_vote_account_stakes(epoch_info)?;
+
+        let mut tracked = identities
+            .iter()
+            .filter(|(_, identity)| tracked_identities.contains(identity))
+            .map(|(vote_pubkey, _)| *vote_pubkey)
+            .chain(tracked_vote_accounts.iter().copied())
+            .collect::<Vec<_>>();
+        tracked.sort_unstable();
+        tracked.dedup();
+
+        // A vote account given by --vote-account may have had no stake in getVoteAccounts, and so no identity yet
+        let (tracked_stakes, tracked_vote_identities) = self.fetch_delegated_stakes(epoch_info, &tracked)?;
+
+        for vote_pubkey in &tracked {
+            vote_stakes.remove(vote_pubkey);
+        }
+        vote_stakes.extend(tracked_stakes);
+        identities.extend(tracked_vote_identities);
+
+        Ok(Stakes::new(epoch, first_slot, slots_in_epoch, vote_stakes, identities))
+    }
+
+    // The effective stake of each of vote_pubkeys as of epoch_info's epoch, totaled from its own stake accounts just
+    // as the full scan totals it, and the identity of each that has any.  The stake accounts of each vote account are
+    // fetched with a filter on the vote account that they are delegated to, so the stake fetched is a tiny fraction of
+    // the full scan's.  Vote accounts without stake are left out.
+    pub fn fetch_delegated_stakes(
+        &self,
+        epoch_info : &EpochInfo,
+        vote_pubkeys : &[Pubkey]
+    ) -> Result<DelegatedStakes, Error>
+    {
+        let stake_history = self.fetch_stake_history()?;
+
+        let new_rate_activation_epoch = self.fetch_new_rate_activation_epoch(&self.fetch_epoch_schedule()?)?;
+
+        let mut vote_stakes = HashMap::<Pubkey, u128>::default();
+
+        for vote_pubkey in vote_pubkeys {
+            let accounts = self.fetch_delegated_stake_accounts(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                STAKE_STATE_VOTER_PUBKEY_OFFSET,
+                vote_pubkey.to_bytes().to_vec()
+            ))])?;
+
+            let mut stake = 0_u128;
+            for (pubkey, account) in accounts {
+                if let Some(delegation) =
+                    decode_delegation(&account.data).map_err(|message| Error::Decode { pubkey, message })?
+                {
+                    stake +=
+                        delegation.effective_stake(epoch_info.epoch, &stake_history, new_rate_activation_epoch) as u128;
+                }
+            }
+
+            if stake > 0 {
+                vote_stakes.insert(*vote_pubkey, stake);
+            }
+        }
+
+        let identities = vote_identities(&self.rpc_client, vote_stakes.keys().copied().collect())?;
+
+        Ok((vote_stakes, identities))
+    }
+}
+
+impl Stakes
+{
+    // Combines the stake of each vote account into that of each validator identity
+    pub fn new(
+        epoch : u64,
+        first_slot : u64,
+        slots_in_epoch : u64,
+        vote_stakes : HashMap<Pubkey, u128>,
+        identities : HashMap<Pubkey, Pubkey>
+    ) -> Self
+    {
+        // The cluster's leader schedule is keyed by validator identity rather than vote account, with the stake of all
+        // vote accounts that vote for the same identity combined
+        let mut identity_stakes = HashMap::<Pubkey, u128>::default();
+
+        for (vote_pubkey, stake) in &vote_stakes {
+            if let Some(identity) = identities.get(vote_pubkey) {
+                *(identity_stakes.entry(*identity).or_insert(0)) += stake;
+            }
+        }
+
+        Stakes { epoch, first_slot, slots_in_epoch, vote_stakes, identities, identity_stakes }
+    }
+
+    pub fn predict(&self) -> Result<PredictedSchedule, Error>
+    {
+        Ok(PredictedSchedule {
+            epoch : self.epoch,
+            first_slot : self.first_slot,
+            leader_schedule : leader_schedule(self.epoch, self.slots_in_epoch, &self.identity_stakes)?
+        })
+    }
+}
+
+impl PredictedSchedule
+{
+    // The leader of each slot of the epoch, in slot order
+    pub fn leaders(&self) -> &[Pubkey]
+    {
+        self.leader_schedule.get_slot_leaders()
+    }
+
+    // The first and last slots of the epoch
+    pub fn slots(&self) -> RangeInclusive<u64>
+    {
+        self.first_slot..=(self.first_slot + (self.leaders().len() as u64) - 1)
+    }
+}
+
+impl LeaderOracle for PredictedSchedule
+{
+    // None if slot is not in the epoch
+    fn leader_at(
+        &self,
+        slot : u64
+    ) -> Option<Pubkey>
+    {
+        slot.checked_sub(self.first_slot).and_then(|index| self.leaders().get(index as usize)).copied()
+    }
+
+    // None if slot is not in the epoch, or its leader leads through the end of the epoch
+    fn next_leader_change(
+        &self,
+        slot : u64
+    ) -> Option<(u64, Pubkey)>
+    {
+        let index = slot.checked_sub(self.first_slot)? as usize;
+        let leaders = self.leaders();
+        let leader = leaders.get(index)?;
+
+        leaders[index..]
+            .iter()
+            .position(|next_leader| next_leader != leader)
+            .map(|offset| (slot + (offset as u64), leaders[index + offset]))
+    }
+}
+
+impl StakeDelegation
+{
+    // The rate stored in each delegation is no longer used: the bank warms up and cools down all stake at the rate
+    // of the epoch, which depends on new_rate_activation_epoch
+    pub fn delegation(&self) -> Delegation
+    {
+        Delegation {
+            voter_pubkey : self.voter_pubkey,
+            stake : self.stake,
+            activation_epoch : self.activation_epoch,
+            deactivation_epoch : self.deactivation_epoch,
+            ..Delegation::default()
+        }
+    }
+
+    // new_rate_activation_epoch is the epoch in which the reduce_stake_warmup_cooldown feature was activated, if it
+    // has been
+    pub fn effective_stake(
+        &self,
+        epoch : u64,
+        stake_history : &StakeHistory,
+        new_rate_activation_epoch : Option<u64>
+    ) -> u64
+    {
+        self.delegation().stake(epoch, Some(stake_history), new_rate_activation_epoch)
+    }
+}
+
+// The Stakes of epoch from its stake per vote account
+#[cfg(feature = "rpc")]
+fn stakes(
+    epoch_schedule : &EpochSchedule,
+    epoch : u64,
+    vote_stakes : HashMap<Pubkey, u128>,
+    identities : HashMap<Pubkey, Pubkey>
+) -> Stakes
+{
+    Stakes::new(
+        epoch,
+        epoch_schedule.get_first_slot_in_epoch(epoch),
+        epoch_schedule.get_slots_in_epoch(epoch),
+        vote_stakes,
+        identities
+    )
+}
+
+// Decodes just the fields of a stake account that are needed, without deserializing the full StakeState.  Returns
+// None for accounts that are not delegated (Uninitialized, Initialized, RewardsPool).
+pub fn decode_delegation(data : &[u8]) -> Result<Option<StakeDelegation>, String>
+{
+    let tag = u32::from_le_bytes(data.get(0..4).ok_or("account data too short")?.try_into().unwrap());
+
+    if tag != STAKE_STATE_TAG_STAKE {
+        return if tag < 4 { Ok(None) } else { Err(format!("invalid stake state {}", tag)) };
+    }
+
+    if data.len() < STAKE_STATE_MIN_LEN {
+        return Err(format!("stake account data is {} bytes, expected at least {}", data.len(), STAKE_STATE_MIN_LEN));
+    }
+
+    let pubkey_at = |offset : usize| Pubkey::new_from_array(data[offset..(offset + 32)].try_into().unwrap());
+    let u64_at = |offset : usize| u64::from_le_bytes(data[offset..(offset + 8)].try_into().unwrap());
+
+    Ok(Some(StakeDelegation {
+        withdrawer : pubkey_at(STAKE_STATE_WITHDRAWER_OFFSET),
+        lockup : Lockup {
+            unix_timestamp : u64_at(STAKE_STATE_LOCKUP_OFFSET) as i64,
+            epoch : u64_at(STAKE_STATE_LOCKUP_OFFSET + 8),
+            custodian : pubkey_at(STAKE_STATE_LOCKUP_OFFSET + 16)
+        },
+        voter_pubkey : pubkey_at(STAKE_STATE_VOTER_PUBKEY_OFFSET),
+        stake : u64_at(STAKE_STATE_STAKE_OFFSET),
+        activation_epoch : u64_at(STAKE_STATE_ACTIVATION_EPOCH_OFFSET),
+        deactivation_epoch : u64_at(STAKE_STATE_DEACTIVATION_EPOCH_OFFSET),
+        warmup_cooldown_rate : f64::from_le_bytes(
+            data[STAKE_STATE_WARMUP_COOLDOWN_RATE_OFFSET..STAKE_STATE_MIN_LEN].try_into().unwrap()
+        )
+    }))
+}
+
+// Only the leading bytes of each account that decode_delegation reads are requested; RPC providers that don't support
+// dataSlice return whole accounts, which decode just the same
+#[cfg(feature = "rpc")]
+pub fn stake_account_config(commitment : CommitmentConfig) -> RpcAccountInfoConfig
+{
+    RpcAccountInfoConfig {
+        encoding : Some(UiAccountEncoding::Base64),
+        data_slice : Some(UiDataSliceConfig { offset : 0, length : STAKE_STATE_MIN_LEN }),
+        commitment : Some(commitment),
+        ..RpcAccountInfoConfig::default()
+    }
+}
+
+// Errors that mean the endpoint doesn't serve getProgramAccounts for the stake program at all are told apart from
+// transient failures, since retrying the same endpoint won't help
+#[cfg(feature = "rpc")]
+fn stake_accounts_error(
+    url : String,
+    e : ClientError
+) -> Error
+{
+    let message = e.to_string();
+
+    let lowercase = message.to_lowercase();
+
+    if ["excluded from account secondary index", "method not found", "disabled", "-32601"]
+        .iter()
+        .any(|unsupported| lowercase.contains(unsupported))
+    {
+        return Error::GetProgramAccountsUnavailable { url, message };
+    }
+
+    Error::Rpc(format!("Failed to fetch stake accounts: {}", message))
+}
+
+// Maps each of the given vote accounts to the identity of the validator that votes with it.  getVoteAccounts covers
+// almost all of them; the rest are read from the vote accounts themselves.  Vote accounts that don't exist, or aren't
+// owned by the vote program, are left out, as the bank does not count stake delegated to them.
+#[cfg(feature = "rpc")]
+fn vote_identities(
+    rpc_client : &RpcClient,
+    vote_pubkeys : Vec<Pubkey>
+) -> Result<HashMap<Pubkey, Pubkey>, Error>
+{
+    let vote_accounts =
+        rpc_client.get_vote_accounts().map_err(|e| Error::Rpc(format!("Failed to fetch vote accounts: {}", e)))?;
+
+    let mut identities = vote_accounts
+        .current
+        .iter()
+        .chain(vote_accounts.delinquent.iter())
+        .filter_map(|vote_account| {
+            Some((vote_account.vote_pubkey.parse::<Pubkey>().ok()?, vote_account.node_pubkey.parse::<Pubkey>().ok()?))
+        })
+        .collect::<HashMap<Pubkey, Pubkey>>();
+
+    let missing =
+        vote_pubkeys.into_iter().filter(|vote_pubkey| !identities.contains_key(vote_pubkey)).collect::<Vec<_>>();
+
+    // getMultipleAccounts accepts at most 100 accounts per request
+    for chunk in missing.chunks(100) {
+        let accounts = rpc_client
+            .get_multiple_accounts(chunk)
+            .map_err(|e| Error::Rpc(format!("Failed to fetch vote accounts: {}", e)))?;
+        for (vote_pubkey, account) in chunk.iter().zip(accounts) {
+            // Every version of VoteState begins with a u32 version tag followed by node_pubkey
+            if let Some(identity) = account
+                .filter(|account| account.owner == solana_sdk::vote::program::id())
+                .and_then(|account| Some(Pubkey::new_from_array(account.data.get(4..36)?.try_into().ok()?)))
+            {
+                identities.insert(*vote_pubkey, identity);
+            }
+        }
+    }
+
+    Ok(identities)
+}
+
+// The absolute slot of the slot_index'th slot of epoch.  This and the other epoch arithmetic below follow the bank's
+// own, including the shorter epochs of a cluster's warmup period.
+pub fn epoch_slot(
+    epoch_schedule : &EpochSchedule,
+    epoch : u64,
+    slot_index : u64
+) -> u64
+{
+    epoch_schedule.get_first_slot_in_epoch(epoch) + slot_index
+}
+
+// The epoch that slot is in, and its index within that epoch
+pub fn slot_epoch(
+    epoch_schedule : &EpochSchedule,
+    slot : u64
+) -> (u64, u64)
+{
+    epoch_schedule.get_epoch_and_slot_index(slot)
+}
+
+// The first and last slots of epoch
+pub fn epoch_slots(
+    epoch_schedule : &EpochSchedule,
+    epoch : u64
+) -> RangeInclusive<u64>
+{
+    epoch_schedule.get_first_slot_in_epoch(epoch)..=epoch_schedule.get_last_slot_in_epoch(epoch)
+}
+
+// When slot can be expected to occur, given that known_slot occurred at known_time and that slots take ms_per_slot.
+// Slot times vary, so this is an estimate that grows less certain the further slot is from known_slot.
+pub fn estimate_slot_time(
+    known_slot : u64,
+    known_time : SystemTime,
+    slot : u64,
+    ms_per_slot : f64
+) -> SystemTime
+{
+    let offset = Duration::from_secs_f64(((slot.abs_diff(known_slot) as f64) * ms_per_slot) / 1000.0);
+
+    if slot >= known_slot {
+        known_time + offset
+    }
+    else {
+        known_time - offset
+    }
+}
+
+// The number of slots of each leader of a schedule, given as the leader of each slot
+pub fn slot_counts(leaders : &[Pubkey]) -> HashMap<Pubkey, u64>
+{
+    let mut slots = HashMap::<Pubkey, u64>::default();
+
+    for leader in leaders {
+        *(slots.entry(*leader).or_insert(0)) += 1;
+    }
+
+    slots
+}
+
+// Cribbed from leader_schedule_utils
+fn sort_stakes(stakes : &mut Vec<(Pubkey, u64)>)
+{
+    // Sort first by stake. If stakes are the same, sort by pubkey to ensure a
+    // deterministic result.
+    // Note: Use unstable sort, because we dedup right after to remove the equal elements.
+    stakes.sort_unstable_by(|(l_pubkey, l_stake), (r_pubkey, r_stake)| {
+        if r_stake == l_stake {
+            r_pubkey.cmp(l_pubkey)
+        }
+        else {
+            r_stake.cmp(l_stake)
+        }
+    });
+
+    // Now that it's sorted, we can do an O(n) dedup.
+    stakes.dedup();
+}
+
+// Mostly cribbed from leader_schedule_utils
+pub fn leader_schedule(
+    epoch : u64,
+    slots_in_epoch : u64,
+    stakes : &HashMap<Pubkey, u128>
+) -> Result<LeaderSchedule, Error>
+{
+    let stakes = schedule_stakes(stakes)?;
+    Ok(LeaderSchedule::new(&stakes, leader_schedule_seed(epoch), slots_in_epoch, NUM_CONSECUTIVE_LEADER_SLOTS))
+}
+
+// The stakes in the form and order that LeaderSchedule::new requires.  Zero stakes are left out, as they can never
+// be chosen, and LeaderSchedule::new panics if no stake is left.
+pub fn schedule_stakes(stakes : &HashMap<Pubkey, u128>) -> Result<Vec<(Pubkey, u64)>, Error>
+{
+    let mut stakes = stakes
+        .iter()
+        .filter(|(_, stake)| **stake > 0)
+        .map(|(pubkey, stake)| {
+            u64::try_from(*stake)
+                .map(|stake| (*pubkey, stake))
+                .map_err(|_| Error::StakeOverflow { pubkey : *pubkey, stake : *stake })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if stakes.is_empty() {
+        return Err(Error::NoStake);
+    }
+    sort_stakes(&mut stakes);
+    Ok(stakes)
+}
+
+pub fn leader_schedule_seed(epoch : u64) -> [u8; 32]
+{
+    let mut seed = [0u8; 32];
+    seed[0..8].copy_from_slice(&epoch.to_le_bytes());
+    seed
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use solana_sdk::stake::state::{Authorized, Meta, Stake, StakeState};
+
+    fn pubkey(byte : u8) -> Pubkey
+    {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn meta() -> Meta
+    {
+        Meta {
+            rent_exempt_reserve : 2_282_880,
+            authorized : Authorized { staker : pubkey(1), withdrawer : pubkey(2) },
+            lockup : Lockup { unix_timestamp : -1_700_000_000, epoch : 3, custodian : pubkey(4) }
+        }
+    }
+
+    // The rate is still encoded in stake accounts, though no longer used
+    #[allow(deprecated)]
+    #[test]
+    fn decode_delegation_reads_each_field_of_stake()
+    {
+        let state = StakeState::Stake(meta(), Stake {
+            delegation : Delegation {
+                voter_pubkey : pubkey(5),
+                stake : 6_000_000_000,
+                activation_epoch : 7,
+                deactivation_epoch : 8,
+                warmup_cooldown_rate : 0.09
+            },
+            credits_observed : 10
+        });
+
+        let delegation = decode_delegation(&bincode::serialize(&state).unwrap()).unwrap().unwrap();
+
+        assert_eq!(delegation.withdrawer, pubkey(2));
+        assert_eq!(delegation.lockup, meta().lockup);
+        assert_eq!(delegation.voter_pubkey, pubkey(5));
+        assert_eq!(delegation.stake, 6_000_000_000);
+        assert_eq!(delegation.activation_epoch, 7);
+        assert_eq!(delegation.deactivation_epoch, 8);
+        assert_eq!(delegation.warmup_cooldown_rate, 0.09);
+    }
+
+    #[test]
+    fn decode_delegation_skips_undelegated_states()
+    {
+        for state in [StakeState::Uninitialized, StakeState::Initialized(meta()), StakeState::RewardsPool] {
+            assert!(decode_delegation(&bincode::serialize(&state).unwrap()).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn decode_delegation_rejects_invalid_data()
+    {
+        assert!(decode_delegation(&4_u32.to_le_bytes()).is_err());
+        assert!(decode_delegation(&[2, 0]).is_err());
+        assert!(decode_delegation(&[2, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn leader_schedule_without_stake_is_an_error()
+    {
+        let mut stakes = HashMap::<Pubkey, u128>::default();
+        assert!(matches!(leader_schedule(1, 32, &stakes), Err(Error::NoStake)));
+
+        stakes.insert(pubkey(1), 0);
+        assert!(matches!(leader_schedule(1, 32, &stakes), Err(Error::NoStake)));
+
+        stakes.insert(pubkey(2), 1);
+        assert!(leader_schedule(1, 32, &stakes).unwrap().get_slot_leaders().iter().all(|leader| *leader == pubkey(2)));
+    }
+
+    #[test]
+    fn epoch_arithmetic_follows_warmup_epochs()
+    {
+        // With warmup, epochs double in length from 32 slots until they reach slots_per_epoch
+        let epoch_schedule = EpochSchedule::custom(256, 256, true);
+
+        assert_eq!(epoch_slots(&epoch_schedule, 0), 0..=31);
+        assert_eq!(epoch_slots(&epoch_schedule, 1), 32..=95);
+        assert_eq!(epoch_slots(&epoch_schedule, 3), 224..=479);
+        assert_eq!(epoch_slots(&epoch_schedule, 4), 480..=735);
+
+        for (epoch, slot_index, slot) in [(0, 0, 0), (1, 63, 95), (3, 0, 224), (5, 10, 746)] {
+            assert_eq!(epoch_slot(&epoch_schedule, epoch, slot_index), slot);
+            assert_eq!(slot_epoch(&epoch_schedule, slot), (epoch, slot_index));
+        }
+    }
+
+    #[test]
+    fn estimate_slot_time_counts_from_the_known_slot()
+    {
+        let known_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        assert_eq!(estimate_slot_time(100, known_time, 100, 400.0), known_time);
+        assert_eq!(estimate_slot_time(100, known_time, 110, 400.0), known_time + Duration::from_secs(4));
+        assert_eq!(estimate_slot_time(100, known_time, 90, 400.0), known_time - Duration::from_secs(4));
+    }
+
+    #[test]
+    fn predicted_schedule_slots_span_the_epoch()
+    {
+        let stakes = Stakes::new(
+            2,
+            64,
+            32,
+            HashMap::from_iter([(pubkey(1), 1)]),
+            HashMap::from_iter([(pubkey(1), pubkey(2))])
+        );
+
+        let schedule = stakes.predict().unwrap();
+
+        assert_eq!(schedule.slots(), 64..=95);
+    }
+
+    #[test]
+    fn predicted_schedule_is_a_leader_oracle()
+    {
+        let stakes = Stakes::new(
+            2,
+            64,
+            64,
+            HashMap::from_iter([(pubkey(1), 1), (pubkey(3), 1)]),
+            HashMap::from_iter([(pubkey(1), pubkey(2)), (pubkey(3), pubkey(4))])
+        );
+
+        let schedule = stakes.predict().unwrap();
+        let oracle : &dyn LeaderOracle = &schedule;
+
+        assert_eq!(oracle.leader_at(63), None);
+        assert_eq!(oracle.leader_at(128), None);
+        assert_eq!(oracle.next_leader_change(63), None);
+
+        for slot in schedule.slots() {
+            let leader = oracle.leader_at(slot).unwrap();
+            assert_eq!(leader, schedule.leaders()[(slot - 64) as usize]);
+            match oracle.next_leader_change(slot) {
+                Some((change, next_leader)) => {
+                    assert!(change > slot);
+                    assert_ne!(next_leader, leader);
+                    assert_eq!(oracle.leader_at(change), Some(next_leader));
+                    assert!((slot..change).all(|between| oracle.leader_at(between) == Some(leader)));
+                },
+                None => assert!((slot..=*schedule.slots().end()).all(|rest| oracle.leader_at(rest) == Some(leader)))
+            }
+        }
+    }
+}
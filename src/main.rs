@@ -1,156 +1,3917 @@
 // Fetches all vote account and for each, fetches validator info.  Stores the details thereof.
-use borsh::BorshDeserialize;
-use solana_client::rpc_client::RpcClient;
+mod output;
+mod parse;
+#[cfg(unix)]
+mod unix_sender;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use output::OutputFormat;
+use parse::SlotArg;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use solana_account_decoder::parse_config::{parse_config, ConfigAccountType};
+use solana_account_decoder::validator_info;
+use solana_cli_config::{Config, CONFIG_FILE};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_client::rpc_config::RpcProgramAccountsConfig;
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_leader_schedule::{
+    estimate_slot_time, leader_schedule, leader_schedule_seed, schedule_stakes, slot_counts, stake_account_config,
+    Error, HashMap, LeaderSchedulePredictor, PredictedSchedule, StakeDelegation, Stakes
+};
 use solana_ledger::leader_schedule::LeaderSchedule;
-use solana_sdk::clock::NUM_CONSECUTIVE_LEADER_SLOTS;
+use solana_rpc_client::http_sender::HttpSender;
+use solana_sdk::account::Account;
+use solana_sdk::clock::{Clock, DEFAULT_MS_PER_SLOT, NUM_CONSECUTIVE_LEADER_SLOTS};
 use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::epoch_info::EpochInfo;
+use solana_sdk::feature_set::FEATURE_NAMES;
+use solana_sdk::hash::hashv;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::stake::state::StakeState;
-use std::collections::HashMap;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 const DEFAULT_MAINNET_RPC_URL : &str = "https://api.mainnet-beta.solana.com";
 const DEFAULT_TESTNET_RPC_URL : &str = "https://api.testnet.solana.com";
 const DEFAULT_DEVNET_RPC_URL : &str = "https://api.devnet.solana.com";
 const DEFAULT_LOCALHOST_RPC_URL : &str = "http://localhost:8899";
 
-const SLOTS_IN_EPOCH : u64 = 432000;
+// Until --timeout and --max-retries say otherwise, each RPC request is given the same 30 seconds as RpcClient gives it
+// by default, and retried up to 3 times
+const DEFAULT_RPC_TIMEOUT : Duration = Duration::from_secs(30);
+const DEFAULT_MAX_RETRIES : u32 = 3;
+
+// The delay before the first retry of a request, doubling with each retry up to the maximum
+const RETRY_BASE_DELAY_MS : u64 = 500;
+const RETRY_MAX_DELAY_MS : u64 = 30_000;
+
+// --timeline draws the epoch in this many cells, which with the surrounding bars fits an 80 column terminal
+const TIMELINE_WIDTH : usize = 72;
+
+// Validator info is seldom changed, so what is fetched is reused for a day
+const VALIDATOR_INFO_CACHE_TTL : Duration = Duration::from_secs(24 * 60 * 60);
+
+// Projection walks every delegation through every epoch up to the last predicted, and assumes that no delegation
+// changes meanwhile, so --epoch and --lookahead reach at most this many epochs past the current one
+const MAX_PROJECTED_EPOCHS : u64 = 100;
+
+// getMultipleAccounts accepts at most 100 accounts per request
+const VERIFY_SAMPLE_SIZE : usize = 100;
+
+// Leader hints file format (all integers little-endian):
+//   header : magic "SLLH" (4 bytes), format version (u16), entry count (u32)
+//   entry  : absolute slot (u64), leader identity (32 bytes), TPU IP address (16 bytes, IPv4 addresses are IPv4-mapped
+//            IPv6), TPU port (u16)
+// An unknown TPU address is all zeroes with port 0.
+const LEADER_HINTS_MAGIC : &[u8; 4] = b"SLLH";
+const LEADER_HINTS_VERSION : u16 = 1;
+
+// The first field of a stakes file, naming its format.  Version 1 is keyed by validator identity; files without it, of
+// earlier formats, are rejected rather than misread.
+const STAKES_FILE_VERSION : &str = "identity-stakes-v1";
+
+// The first field of a cached stakes file.  Cache files of any other version are ignored and replaced by the next run
+// that fetches stakes.
+const STAKES_CACHE_VERSION : &str = "stakes-cache-v2";
+
+// The number of vote accounts in each checksummed chunk of a cached stakes file, which is the most that a damaged chunk
+// costs to refetch
+const STAKES_CACHE_CHUNK_SIZE : usize = 64;
+
+// Must be kept the same as the solana-sdk version pinned in Cargo.toml
+const SOLANA_SDK_VERSION : &str = "1.16.27";
+
+// Counts every heap allocation made by the process, so that --profile can report allocations per phase
+struct CountingAllocator;
+
+static ALLOCATIONS : AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator
+{
+    unsafe fn alloc(
+        &self,
+        layout : Layout
+    ) -> *mut u8
+    {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(
+        &self,
+        ptr : *mut u8,
+        layout : Layout
+    )
+    {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr : *mut u8,
+        layout : Layout,
+        new_size : usize
+    ) -> *mut u8
+    {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL : CountingAllocator = CountingAllocator;
+
+// Requests made and response bytes received, per RPC method
+type RpcUsage = Arc<Mutex<BTreeMap<String, (u64, u64)>>>;
+
+// How each RPC request is sent, set by --timeout and --max-retries
+#[derive(Clone, Copy)]
+struct RetryPolicy
+{
+    timeout : Duration,
+
+    max_retries : u32
+}
+
+// Sends RPC requests over HTTP as RpcClient normally would (or over a Unix domain socket for unix:// URLs), retrying
+// those that fail transiently and tallying the usage of each method for --rpc-usage
+struct CountingSender
+{
+    inner : Box<dyn RpcSender + Send + Sync>,
+
+    usage : RpcUsage,
+
+    retry_policy : RetryPolicy
+}
+
+// Counts the bytes written to it and discards them, to size a response without holding its serialization
+struct ByteCounter(u64);
+
+// Records elapsed time and allocation count of each phase of a run; only reports when enabled
+struct Profiler
+{
+    enabled : bool,
+
+    phase_start : Instant,
+
+    phase_allocations : u64,
+
+    phases : Vec<(&'static str, Duration, u64)>
+}
+
+// The time zone in which --times shows estimated times
+#[derive(Clone, Copy)]
+enum DisplayZone
+{
+    Utc,
+    Local,
+    Offset(FixedOffset)
+}
+
+// How human-readable reports show amounts of stake: exactly in lamports, or in SOL rounded to the number of decimal
+// places given by --sol-decimals.  Machine-readable output is always in lamports.
+#[derive(Clone, Copy)]
+enum StakeUnit
+{
+    Lamports,
+    Sol(u32)
+}
+
+// The order of the --summary table, selected with --sort-by
+#[derive(Clone, Copy)]
+enum SummarySort
+{
+    Slots,
+    Stake,
+    Pubkey
+}
+
+// Estimates when slots will occur from a slot observed at a known time and the cluster's recent rate of slots
+struct SlotClock
+{
+    slot : u64,
+
+    time : DateTime<Utc>,
+
+    ms_per_slot : f64,
+
+    zone : DisplayZone
+}
+
+// The validators that the filter report lists the leader slots of, given by --identity and --vote-account, and how
+struct ValidatorSlotsReport<'a>
+{
+    identities : &'a [Pubkey],
+
+    vote_accounts : &'a [Pubkey],
+
+    // Set by --times, to show the estimated start time of each group of slots
+    slot_clock : Option<&'a SlotClock>,
+
+    // Set by --timeline
+    timeline : bool
+}
 
 struct Args
 {
-    url : String
+    url : String,
+
+    report_zero_stake : bool,
+
+    threats : bool,
+
+    by_withdrawer : bool,
+
+    geoip : Option<String>,
+
+    resolve_names : bool,
+
+    export_leader_hints : Option<String>,
+
+    profile : bool,
+
+    explain : bool,
+
+    lockups : bool,
+
+    rpc_usage : bool,
+
+    include_current_epoch_activations : bool,
+
+    will_i_lead : Option<Pubkey>,
+
+    adjacency : Option<Pubkey>,
+
+    save_stakes : Option<String>,
+
+    impact : Option<(String, String)>,
+
+    from_stakes : Option<String>,
+
+    verify_url : Option<String>,
+
+    head : Option<usize>,
+
+    tail : Option<usize>,
+
+    monte_carlo : Option<usize>,
+
+    output_format : OutputFormat,
+
+    // Set by --out-dir: the directory to write the schedule listing to instead of stdout, and the formats to write it
+    // in, one file each
+    out_dir : Option<(String, Vec<OutputFormat>)>,
+
+    // Path, start slot and end slot
+    export_window : Option<(String, SlotArg, SlotArg)>,
+
+    dump_weights : Option<String>,
+
+    versions : bool,
+
+    probe : bool,
+
+    slot_time : bool,
+
+    infer_stakes : bool,
+
+    verify_cache : bool,
+
+    // Set by --consistency-check to the number of runs, which cycle through the URL and any --consistency-url
+    consistency_check : Option<usize>,
+
+    consistency_urls : Vec<String>,
+
+    // Validators given by --identity and --vote-account, whose slots alone are shown
+    filter_identities : Vec<Pubkey>,
+
+    filter_vote_accounts : Vec<Pubkey>,
+
+    // Set by --times, to the zone given by --timezone or UTC
+    times : Option<DisplayZone>,
+
+    timeline : bool,
+
+    // Lists the plain schedule one leader per slot rather than one line per group of slots
+    per_slot : bool,
+
+    // Set by --summary, to the order given by --sort-by or by slots
+    summary : Option<SummarySort>,
+
+    stake_unit : StakeUnit,
+
+    ties : bool,
+
+    diff_current : bool,
+
+    checklist : Option<Pubkey>,
+
+    runbook : Option<Pubkey>,
+
+    // Takes the stake of each vote account from getVoteAccounts, as the RPC node totals it, instead of totaling every
+    // stake account; see README.md for the accuracy this gives up
+    fast : bool,
+
+    // Set by --epoch and --lookahead: the first epoch to predict, if not the next, and the number of epochs
+    epoch : Option<u64>,
+
+    lookahead : Option<u64>,
+
+    // Set by --verify, with the number of mismatched slots that --verify-tolerance allows
+    verify : Option<usize>,
+
+    retry_policy : RetryPolicy,
+
+    no_cache : bool,
+
+    refresh : bool
+}
+
+// Stable identifiers for each class of fatal error, reported with --errors json
+#[derive(Clone, Copy)]
+enum ErrorCode
+{
+    InvalidArguments,
+    RpcError,
+    DecodeError,
+    StakeOverflow,
+    GeoipError,
+    ReadError,
+    GetProgramAccountsUnavailable,
+    WriteError,
+    ScheduleMismatch,
+    NoStake
+}
+
+impl ErrorCode
+{
+    fn as_str(self) -> &'static str
+    {
+        match self {
+            ErrorCode::InvalidArguments => "invalid_arguments",
+            ErrorCode::RpcError => "rpc_error",
+            ErrorCode::DecodeError => "decode_error",
+            ErrorCode::StakeOverflow => "stake_overflow",
+            ErrorCode::GeoipError => "geoip_error",
+            ErrorCode::ReadError => "read_error",
+            ErrorCode::GetProgramAccountsUnavailable => "get_program_accounts_unavailable",
+            ErrorCode::WriteError => "write_error",
+            ErrorCode::ScheduleMismatch => "schedule_mismatch",
+            ErrorCode::NoStake => "no_stake"
+        }
+    }
 }
 
-fn error_exit(msg : String) -> !
+// Set by --errors json; fatal errors are then written to stdout as a JSON object instead of as text to stderr
+static JSON_ERRORS : AtomicBool = AtomicBool::new(false);
+
+fn error_exit(
+    code : ErrorCode,
+    msg : String
+) -> !
 {
-    eprintln!("{}", msg);
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        println!(
+            "{{\"error\":{{\"code\":\"{}\",\"message\":{}}}}}",
+            code.as_str(),
+            json_string(msg.strip_prefix("ERROR: ").unwrap_or(&msg))
+        );
+    }
+    else {
+        eprintln!("{}", msg);
+    }
     std::process::exit(-1);
 }
 
+// Exits with the error code that corresponds to an error from the library
+fn predictor_error(e : Error) -> !
+{
+    match e {
+        Error::Rpc(_) => error_exit(ErrorCode::RpcError, format!("ERROR: {}", e)),
+        Error::Decode { .. } => error_exit(ErrorCode::DecodeError, format!("ERROR: {}", e)),
+        Error::StakeOverflow { .. } => error_exit(ErrorCode::StakeOverflow, format!("ERROR: {}", e)),
+        Error::GetProgramAccountsUnavailable { .. } => error_exit(
+            ErrorCode::GetProgramAccountsUnavailable,
+            format!(
+                "ERROR: {}\nEvery stake account is needed to compute the schedule.  Use an endpoint that allows it, \
+                 such as your own RPC node or the public cluster endpoint (-u m, -u t, -u d), or rerun with --fast \
+                 (approximate stakes).",
+                e
+            )
+        ),
+        Error::EpochNotPredictable { .. } => error_exit(ErrorCode::InvalidArguments, format!("ERROR: {}", e)),
+        Error::NoStake => error_exit(ErrorCode::NoStake, format!("ERROR: {}", e))
+    }
+}
+
+fn json_string(s : &str) -> String
+{
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
 fn parse_args() -> Result<Args, String>
 {
+    // The error format is found first so that errors in the rest of the arguments are reported in it too
+    let all_args = std::env::args().collect::<Vec<_>>();
+    if all_args.windows(2).any(|pair| (pair[0] == "--errors") && (pair[1] == "json")) {
+        JSON_ERRORS.store(true, Ordering::Relaxed);
+    }
+
+    // --version takes no other arguments except --json, which selects its format, and prints and exits
+    if all_args.iter().any(|arg| arg == "--version") {
+        if let Some(arg) = all_args.iter().skip(1).find(|arg| (*arg != "--version") && (*arg != "--json")) {
+            return Err(format!("ERROR: Unexpected argument {} with --version", arg));
+        }
+        print_version(all_args.iter().any(|arg| arg == "--json"));
+        std::process::exit(0);
+    }
+
     let mut args = std::env::args();
 
-    args.nth(0);
+    args.next();
 
     let mut url = None;
 
-    while let Some(arg) = args.nth(0) {
+    let mut config = None;
+
+    let mut report_zero_stake = false;
+
+    let mut threats = false;
+
+    let mut by_withdrawer = false;
+
+    let mut geoip = None;
+
+    let mut diff_current = false;
+
+    let mut resolve_names = false;
+
+    let mut export_leader_hints = None;
+
+    let mut profile = false;
+
+    let mut explain = false;
+
+    let mut lockups = false;
+
+    let mut rpc_usage = false;
+
+    let mut include_current_epoch_activations = false;
+
+    let mut will_i_lead = None;
+
+    let mut adjacency = None;
+
+    let mut save_stakes = None;
+
+    let mut impact = None;
+
+    let mut from_stakes = None;
+
+    let mut verify_url = None;
+
+    let mut head = None;
+
+    let mut tail = None;
+
+    let mut monte_carlo = None;
+
+    let mut output_formats = None;
+
+    let mut out_dir = None;
+
+    let mut export_window = None;
+
+    let mut dump_weights = None;
+
+    let mut start_slot = None;
+
+    let mut end_slot = None;
+
+    let mut versions = false;
+
+    let mut probe = false;
+
+    let mut slot_time = false;
+
+    let mut infer_stakes = false;
+
+    let mut verify_cache = false;
+
+    let mut consistency_check = None;
+
+    let mut consistency_urls = vec![];
+
+    let mut filter_identities = vec![];
+
+    let mut filter_vote_accounts = vec![];
+
+    let mut times = false;
+
+    let mut timeline = false;
+
+    let mut per_slot = false;
+
+    let mut timezone = None;
+
+    let mut summary = false;
+
+    let mut sort_by = None;
+
+    let mut sol_decimals = None;
+
+    let mut ties = false;
+
+    let mut checklist = None;
+
+    let mut runbook = None;
+
+    let mut fast = false;
+
+    let mut epoch = None;
+
+    let mut lookahead = None;
+
+    let mut verify = false;
+
+    let mut verify_tolerance = None;
+
+    let mut no_cache = false;
+
+    let mut refresh = false;
+
+    let mut timeout = None;
+
+    let mut max_retries = None;
+
+    // Every option given, for checking the options of the modes below against
+    let mut given = vec![];
+
+    while let Some(arg) = args.next() {
+        given.push(arg.clone());
         match arg.as_str() {
-            "-u" | "--url" => {
-                if url.is_some() {
-                    eprintln!("ERROR: Duplicate {} argument", arg);
-                    std::process::exit(-1);
-                }
-                url = match args.nth(0) {
-                    None => error_exit(format!("ERROR: {} requires an argument", arg)),
-                    Some(arg) => Some(arg.clone())
+            "--config" => set_once(&arg, &mut config, arg_value(&arg, args.next())),
+            "-u" | "--url" => set_once(&arg, &mut url, arg_value(&arg, args.next())),
+            "--report-zero-stake" => report_zero_stake = true,
+            "--threats" => threats = true,
+            "--by-withdrawer" => by_withdrawer = true,
+            "--geoip" => set_once(&arg, &mut geoip, arg_value(&arg, args.next())),
+            "--resolve-names" => resolve_names = true,
+            "--export-leader-hints" => set_once(&arg, &mut export_leader_hints, arg_value(&arg, args.next())),
+            "--profile" => profile = true,
+            "--explain" => explain = true,
+            "--lockups" => lockups = true,
+            "--versions" => versions = true,
+            "--probe" => probe = true,
+            "--slot-time" => slot_time = true,
+            "--infer-stakes" => infer_stakes = true,
+            "--verify-cache" => verify_cache = true,
+            "--consistency-check" => {
+                let runs = args.next().and_then(|runs| runs.parse::<usize>().ok()).filter(|runs| *runs > 1);
+                let runs = runs.unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a number of runs above 1", arg))
+                });
+                set_once(&arg, &mut consistency_check, runs);
+            },
+            "--consistency-url" => consistency_urls.push(arg_value(&arg, args.next())),
+            "--rpc-usage" => rpc_usage = true,
+            "--include-current-epoch-activations" => include_current_epoch_activations = true,
+            "--will-i-lead" => set_once(&arg, &mut will_i_lead, identity_arg(&arg, args.next())),
+            "--dump-weights" => set_once(&arg, &mut dump_weights, arg_value(&arg, args.next())),
+            "--save-stakes" => set_once(&arg, &mut save_stakes, arg_value(&arg, args.next())),
+            "--impact" => {
+                let paths = match (args.next(), args.next()) {
+                    (Some(before), Some(after)) => (before, after),
+                    _ => error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires two arguments", arg))
                 };
+                set_once(&arg, &mut impact, paths);
+            },
+            "--from-stakes" => set_once(&arg, &mut from_stakes, arg_value(&arg, args.next())),
+            "--verify-url" => set_once(&arg, &mut verify_url, arg_value(&arg, args.next())),
+            "--head" | "--tail" => {
+                let count = args.next().and_then(|count| count.parse::<usize>().ok()).unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a number of slots", arg))
+                });
+                let slot_count = if arg == "--head" { &mut head } else { &mut tail };
+                set_once(&arg, slot_count, count);
+            },
+            "--timeout" => {
+                let duration = args.next().unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a duration", arg))
+                });
+                let duration = parse::parse_duration(&duration)
+                    .and_then(|duration| {
+                        if duration.is_zero() {
+                            Err(format!("ERROR: {} must be longer than zero", arg))
+                        }
+                        else {
+                            Ok(duration)
+                        }
+                    })
+                    .unwrap_or_else(|e| error_exit(ErrorCode::InvalidArguments, e));
+                set_once(&arg, &mut timeout, duration);
+            },
+            "--max-retries" => {
+                let retries = args.next().and_then(|retries| retries.parse::<u32>().ok()).unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a number of retries", arg))
+                });
+                set_once(&arg, &mut max_retries, retries);
+            },
+            "--monte-carlo" => {
+                let runs = args.next().and_then(|runs| runs.parse::<usize>().ok()).filter(|runs| *runs > 0);
+                let runs = runs.unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a number of runs", arg))
+                });
+                set_once(&arg, &mut monte_carlo, runs);
+            },
+            "--output-format" => {
+                let formats = arg_value(&arg, args.next())
+                    .split(',')
+                    .map(str::parse::<OutputFormat>)
+                    .collect::<Result<Vec<_>, _>>()?;
+                set_once(&arg, &mut output_formats, formats);
+            },
+            "--out-dir" => set_once(&arg, &mut out_dir, arg_value(&arg, args.next())),
+            "--export-window" => set_once(&arg, &mut export_window, arg_value(&arg, args.next())),
+            "--start-slot" | "--end-slot" => {
+                let slot = args.next().unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a slot", arg))
+                });
+                let slot = slot.parse::<SlotArg>().unwrap_or_else(|e| error_exit(ErrorCode::InvalidArguments, e));
+                let window_slot = if arg == "--start-slot" { &mut start_slot } else { &mut end_slot };
+                set_once(&arg, window_slot, slot);
             },
-            _ => error_exit(format!("ERROR: Unexpected extra argument {}", arg))
+            "--identity" => filter_identities.push(identity_arg(&arg, args.next())),
+            "--vote-account" => {
+                let vote_account = arg_value(&arg, args.next());
+                filter_vote_accounts.push(vote_account.parse::<Pubkey>().unwrap_or_else(|_| {
+                    error_exit(
+                        ErrorCode::InvalidArguments,
+                        format!("ERROR: Invalid vote account pubkey {}", vote_account)
+                    )
+                }));
+            },
+            "--checklist" => set_once(&arg, &mut checklist, identity_arg(&arg, args.next())),
+            "--runbook" => set_once(&arg, &mut runbook, identity_arg(&arg, args.next())),
+            "--summary" => summary = true,
+            "--ties" => ties = true,
+            "--diff-current" => diff_current = true,
+            "--fast" => fast = true,
+            "--verify" => verify = true,
+            "--verify-tolerance" => {
+                let slots = args.next().and_then(|slots| slots.parse::<usize>().ok()).unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a number of slots", arg))
+                });
+                set_once(&arg, &mut verify_tolerance, slots);
+            },
+            "--no-cache" => no_cache = true,
+            "--refresh" => refresh = true,
+            "--epoch" => {
+                let number = args.next().and_then(|number| number.parse::<u64>().ok()).unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires an epoch number", arg))
+                });
+                set_once(&arg, &mut epoch, number);
+            },
+            "--lookahead" => {
+                let epochs = args.next().and_then(|epochs| epochs.parse::<u64>().ok()).filter(|epochs| *epochs > 0);
+                let epochs = epochs.unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires a number of epochs", arg))
+                });
+                set_once(&arg, &mut lookahead, epochs);
+            },
+            "--sol-decimals" => {
+                let decimals =
+                    args.next().and_then(|decimals| decimals.parse::<u32>().ok()).filter(|decimals| *decimals <= 9);
+                let decimals = decimals.unwrap_or_else(|| {
+                    error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires 0 to 9 decimal places", arg))
+                });
+                set_once(&arg, &mut sol_decimals, decimals);
+            },
+            "--sort-by" => set_once(&arg, &mut sort_by, arg_value(&arg, args.next()).parse::<SummarySort>()?),
+            "--times" => times = true,
+            "--timeline" => timeline = true,
+            "--per-slot" => per_slot = true,
+            "--timezone" => set_once(&arg, &mut timezone, arg_value(&arg, args.next()).parse::<DisplayZone>()?),
+            "--adjacency" => set_once(&arg, &mut adjacency, identity_arg(&arg, args.next())),
+            "--errors" => match args.next().as_deref() {
+                Some("text") | Some("json") => (),
+                _ => error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires text or json", arg))
+            },
+            _ => error_exit(ErrorCode::InvalidArguments, format!("ERROR: Unexpected extra argument {}", arg))
         }
     }
 
-    Ok(Args { url : get_url(url) })
-}
+    // Several formats can only be written at once to files, one per format, in the directory given with --out-dir.
+    // Nothing but the schedule listing is written to those, so output_format, which the other output is checked
+    // against, is only that of stdout.
+    let (output_format, out_dir) = match (output_formats, out_dir) {
+        (Some(formats), None) if formats.len() > 1 => {
+            return Err("ERROR: --output-format can only list several formats with --out-dir".to_string())
+        },
+        (formats, None) => (formats.map(|formats| formats[0]), None),
+        (formats, Some(dir)) => (None, Some((dir, formats.unwrap_or_else(|| vec![OutputFormat::Text]))))
+    };
 
-fn get_url(url : Option<String>) -> String
-{
-    url.map_or_else(
-        || DEFAULT_MAINNET_RPC_URL.to_string(),
-        |url| match url.as_str() {
-            "l" | "localhost" => DEFAULT_LOCALHOST_RPC_URL.to_string(),
-            "d" | "devnet" => DEFAULT_DEVNET_RPC_URL.to_string(),
-            "t" | "testnet" => DEFAULT_TESTNET_RPC_URL.to_string(),
-            "m" | "mainnet" => DEFAULT_MAINNET_RPC_URL.to_string(),
-            _ => url.clone()
-        }
-    )
-}
+    let export_window = match (export_window, start_slot, end_slot) {
+        (Some(path), Some(start_slot), Some(end_slot)) => Some((path, start_slot, end_slot)),
+        (None, None, None) => None,
+        _ => return Err("ERROR: --export-window requires --start-slot and --end-slot".to_string())
+    };
 
-fn main()
-{
-    let args = parse_args().unwrap_or_else(|e| {
-        eprintln!("{}", e);
-        std::process::exit(-1);
-    });
+    // Times are shown only for the slot groups of the validators given
+    let times = match (times, timezone) {
+        (false, None) => None,
+        (false, Some(_)) => return Err("ERROR: --timezone requires --times".to_string()),
+        (true, _) if filter_identities.is_empty() && filter_vote_accounts.is_empty() => {
+            return Err("ERROR: --times requires --identity or --vote-account".to_string())
+        },
+        (true, timezone) => Some(timezone.unwrap_or(DisplayZone::Utc))
+    };
 
-    let rpc_client = RpcClient::new_with_commitment(args.url, CommitmentConfig::finalized());
+    if timeline && filter_identities.is_empty() && filter_vote_accounts.is_empty() {
+        return Err("ERROR: --timeline requires --identity or --vote-account".to_string());
+    }
 
-    // Fetch current epoch
-    let current_epoch = rpc_client
-        .get_epoch_info()
-        .unwrap_or_else(|e| error_exit(format!("ERROR: Failed to fetch epoch info: {}", e.to_string())))
-        .epoch;
+    if !consistency_urls.is_empty() && consistency_check.is_none() {
+        return Err("ERROR: --consistency-url requires --consistency-check".to_string());
+    }
 
-    // Fetch stakes in current epoch
-    let response = rpc_client
-        .get_program_accounts(&solana_sdk::stake::program::id())
-        .unwrap_or_else(|e| error_exit(format!("ERROR: Failed to fetch stake accounts: {}", e.to_string())));
+    let summary = match (summary, sort_by) {
+        (false, None) => None,
+        (false, Some(_)) => return Err("ERROR: --sort-by requires --summary".to_string()),
+        (true, sort_by) => Some(sort_by.unwrap_or(SummarySort::Slots))
+    };
 
-    let mut stakes = HashMap::<Pubkey, u64>::new();
+    if verify_tolerance.is_some() && !verify {
+        return Err("ERROR: --verify-tolerance requires --verify".to_string());
+    }
 
-    for (pubkey, account) in response {
-        // Zero-length accounts owned by the stake program are system accounts that were re-assigned and are to be
-        // ignored
-        if account.data.len() == 0 {
-            continue;
-        }
+    if sol_decimals.is_some() &&
+        !(threats || by_withdrawer || will_i_lead.is_some() || summary.is_some() || ties || lockups || impact.is_some())
+    {
+        return Err("ERROR: --sol-decimals requires a report that shows stake".to_string());
+    }
 
-        match StakeState::deserialize(&mut account.data.as_slice())
-            .unwrap_or_else(|e| error_exit(format!("Failed to decode stake account {}: {}", pubkey, e)))
-        {
-            StakeState::Stake(_, stake) => {
-                // Ignore stake accounts activated in this epoch (or later, to include activation_epoch of
-                // u64::MAX which indicates no activation ever happened)
-                if stake.delegation.activation_epoch >= current_epoch {
-                    continue;
-                }
-                // Ignore stake accounts deactivated before this epoch
-                if stake.delegation.deactivation_epoch < current_epoch {
-                    continue;
-                }
-                // Add the stake in this stake account to the total for the delegated-to vote account
-                *(stakes.entry(stake.delegation.voter_pubkey.clone()).or_insert(0)) += stake.delegation.stake;
-            },
-            _ => ()
+    if no_cache && refresh {
+        return Err("ERROR: --refresh cannot be used with --no-cache".to_string());
+    }
+
+    // Only the full scan of stake accounts has the detail of each stake account that these need
+    if fast && (by_withdrawer || lockups || verify_url.is_some() || include_current_epoch_activations) {
+        return Err("ERROR: --fast cannot be used with --by-withdrawer, --lockups, --verify-url, or \
+                    --include-current-epoch-activations"
+            .to_string());
+    }
+
+    // Each of these modes does only its own work and exits, so it takes no options but these and those that select
+    // the endpoint and how it is used.  The reports below are instead written in place of the schedule listing, and
+    // --diff-current, --epoch and --lookahead replace the schedule computed, each with its own checks further down.
+    let modes : [(bool, &str, &[&str]); 8] = [
+        (impact.is_some(), "--impact", &["--output-format", "--sol-decimals"]),
+        (from_stakes.is_some(), "--from-stakes", &["--output-format", "--head", "--tail", "--per-slot"]),
+        (verify, "--verify", &["--verify-tolerance", "--verify-url", "--profile", "--rpc-usage"]),
+        (probe, "--probe", &[]),
+        (slot_time, "--slot-time", &[]),
+        (consistency_check.is_some(), "--consistency-check", &["--consistency-url"]),
+        (infer_stakes, "--infer-stakes", &[]),
+        (verify_cache, "--verify-cache", &[])
+    ];
+
+    let shared = ["-u", "--url", "--config", "--errors", "--timeout", "--max-retries"];
+
+    for (_, mode, options) in modes.iter().filter(|(in_use, _, _)| *in_use) {
+        let ignored = given.iter().map(String::as_str).find(|option| {
+            (option != mode) && !options.contains(option) && !shared.contains(option)
+        });
+        if let Some(option) = ignored {
+            return Err(format!("ERROR: {} cannot be used with {}", mode, option));
         }
     }
 
-    println!("The leader schedule for {} will be:", (current_epoch + 1));
+    if impact.is_some() && matches!(output_format, Some(OutputFormat::Json) | Some(OutputFormat::Csv)) {
+        return Err("ERROR: --impact writes only text or diff".to_string());
+    }
+
+    // Each of these reports is written in place of the schedule listing, so only one of them can be
+    let reports = [
+        (threats, "--threats"),
+        (by_withdrawer, "--by-withdrawer"),
+        (will_i_lead.is_some(), "--will-i-lead"),
+        (!filter_identities.is_empty() || !filter_vote_accounts.is_empty(), "--identity or --vote-account"),
+        (checklist.is_some(), "--checklist"),
+        (runbook.is_some(), "--runbook"),
+        (diff_current, "--diff-current"),
+        (summary.is_some(), "--summary"),
+        (ties, "--ties"),
+        (versions, "--versions"),
+        (monte_carlo.is_some(), "--monte-carlo"),
+        (adjacency.is_some(), "--adjacency"),
+        (geoip.is_some(), "--geoip"),
+        (resolve_names, "--resolve-names"),
+        (report_zero_stake, "--report-zero-stake"),
+        (lockups, "--lockups")
+    ]
+    .into_iter()
+    .filter_map(|(given, report)| given.then_some(report))
+    .collect::<Vec<_>>();
 
-    for leader in leader_schedule(current_epoch + 1, stakes).get_slot_leaders() {
-        println!("{}", leader);
+    if reports.len() > 1 {
+        return Err(format!("ERROR: Only one report can be written at a time, but {} were given", reports.join(", ")));
     }
-}
 
-// Cribbed from leader_schedule_utils
-fn sort_stakes(stakes : &mut Vec<(Pubkey, u64)>)
-{
-    // Sort first by stake. If stakes are the same, sort by pubkey to ensure a
-    // deterministic result.
-    // Note: Use unstable sort, because we dedup right after to remove the equal elements.
-    stakes.sort_unstable_by(|(l_pubkey, l_stake), (r_pubkey, r_stake)| {
-        if r_stake == l_stake {
-            r_pubkey.cmp(l_pubkey)
+    // The machine-readable formats are for the schedule alone, so nothing else may be written to stdout with them
+    let other_output = threats ||
+        by_withdrawer ||
+        will_i_lead.is_some() ||
+        monte_carlo.is_some() ||
+        adjacency.is_some() ||
+        geoip.is_some() ||
+        resolve_names ||
+        versions ||
+        summary.is_some() ||
+        ties ||
+        checklist.is_some() ||
+        runbook.is_some() ||
+        probe ||
+        slot_time ||
+        infer_stakes ||
+        verify_cache ||
+        consistency_check.is_some() ||
+        !filter_identities.is_empty() ||
+        !filter_vote_accounts.is_empty() ||
+        explain ||
+        report_zero_stake ||
+        lockups;
+
+    if let Some((_, formats)) = &out_dir {
+        if formats.contains(&OutputFormat::Diff) {
+            return Err("ERROR: --output-format diff can only be used with --impact".to_string());
         }
-        else {
-            r_stake.cmp(l_stake)
+        if other_output || diff_current || epoch.is_some() || lookahead.is_some() {
+            return Err(
+                "ERROR: --out-dir can only be used for the plain schedule listing of the next epoch".to_string()
+            );
         }
-    });
+        if per_slot && !formats.contains(&OutputFormat::Text) {
+            return Err("ERROR: --per-slot can only be used for the plain schedule listing in text".to_string());
+        }
+    }
 
-    // Now that it's sorted, we can do an O(n) dedup.
-    stakes.dedup();
-}
+    if (output_format == Some(OutputFormat::Diff)) && impact.is_none() {
+        return Err("ERROR: --output-format diff can only be used with --impact".to_string());
+    }
 
-// Mostly cribbed from leader_schedule_utils
-fn leader_schedule(
-    epoch : u64,
-    stakes : HashMap<Pubkey, u64>
-) -> LeaderSchedule
-{
-    let mut seed = [0u8; 32];
-    seed[0..8].copy_from_slice(&epoch.to_le_bytes());
-    let mut stakes : Vec<_> = stakes.iter().map(|(pubkey, stake)| (*pubkey, *stake)).collect();
-    sort_stakes(&mut stakes);
-    LeaderSchedule::new(&stakes, seed, SLOTS_IN_EPOCH, NUM_CONSECUTIVE_LEADER_SLOTS)
+    if per_slot && (other_output || output_format.is_some_and(|output_format| output_format != OutputFormat::Text)) {
+        return Err("ERROR: --per-slot can only be used for the plain schedule listing in text".to_string());
+    }
+
+    if other_output && output_format.is_some_and(|output_format| output_format != OutputFormat::Text) {
+        return Err("ERROR: --output-format json or csv can only be used for the plain schedule listing".to_string());
+    }
+
+    // The stakes of the epochs predicted with these are projected rather than observed, so only their schedules are
+    // shown
+    if (epoch.is_some() || lookahead.is_some()) &&
+        (other_output ||
+            fast ||
+            include_current_epoch_activations ||
+            save_stakes.is_some() ||
+            export_leader_hints.is_some() ||
+            export_window.is_some() ||
+            dump_weights.is_some())
+    {
+        return Err("ERROR: --epoch and --lookahead can only be used for the plain schedule listing".to_string());
+    }
+
+    // The current epoch's stakes are reconstructed from the full scan of stake accounts, and the comparison is all that
+    // is written, in text or json
+    if diff_current &&
+        (other_output ||
+            !matches!(output_format, None | Some(OutputFormat::Text) | Some(OutputFormat::Json)) ||
+            per_slot ||
+            verify ||
+            fast ||
+            impact.is_some() ||
+            from_stakes.is_some() ||
+            epoch.is_some() ||
+            lookahead.is_some())
+    {
+        return Err(
+            "ERROR: --diff-current cannot be used with other reports, --fast, or --output-format csv".to_string()
+        );
+    }
+
+    Ok(Args {
+        url : get_url(match url {
+            Some(url) => Some(url),
+            None => default_url(config)?
+        })?,
+        report_zero_stake,
+        threats,
+        by_withdrawer,
+        geoip,
+        resolve_names,
+        export_leader_hints,
+        profile,
+        explain,
+        lockups,
+        rpc_usage,
+        include_current_epoch_activations,
+        will_i_lead,
+        adjacency,
+        save_stakes,
+        impact,
+        from_stakes,
+        verify_url : verify_url.map(|url| get_url(Some(url))).transpose()?,
+        head,
+        tail,
+        monte_carlo,
+        output_format : output_format.unwrap_or(OutputFormat::Text),
+        out_dir,
+        export_window,
+        dump_weights,
+        versions,
+        probe,
+        slot_time,
+        infer_stakes,
+        verify_cache,
+        consistency_check,
+        consistency_urls : consistency_urls.into_iter().map(|url| get_url(Some(url))).collect::<Result<_, _>>()?,
+        filter_identities,
+        filter_vote_accounts,
+        times,
+        timeline,
+        per_slot,
+        summary,
+        stake_unit : sol_decimals.map_or(StakeUnit::Lamports, StakeUnit::Sol),
+        ties,
+        diff_current,
+        checklist,
+        runbook,
+        fast,
+        epoch,
+        lookahead,
+        verify : if verify { Some(verify_tolerance.unwrap_or(0)) } else { None },
+        retry_policy : RetryPolicy {
+            timeout : timeout.unwrap_or(DEFAULT_RPC_TIMEOUT),
+            max_retries : max_retries.unwrap_or(DEFAULT_MAX_RETRIES)
+        },
+        no_cache,
+        refresh
+    })
+}
+
+// The value given for arg, which requires one
+fn arg_value(
+    arg : &str,
+    value : Option<String>
+) -> String
+{
+    value.unwrap_or_else(|| error_exit(ErrorCode::InvalidArguments, format!("ERROR: {} requires an argument", arg)))
+}
+
+// Sets option to the value given for arg, which can be given only once
+fn set_once<T>(
+    arg : &str,
+    option : &mut Option<T>,
+    value : T
+)
+{
+    if option.replace(value).is_some() {
+        error_exit(ErrorCode::InvalidArguments, format!("ERROR: Duplicate {} argument", arg));
+    }
+}
+
+fn identity_arg(
+    arg : &str,
+    identity : Option<String>
+) -> Pubkey
+{
+    let identity = arg_value(arg, identity);
+    identity.parse::<Pubkey>().unwrap_or_else(|_| {
+        error_exit(ErrorCode::InvalidArguments, format!("ERROR: Invalid identity pubkey {}", identity))
+    })
+}
+
+// The JSON form is for automation to gate on: output_formats lists the version of each versioned file format that
+// is written, whether as output (leader hints), for a later run to read (stakes files), or to the cache
+fn print_version(json : bool)
+{
+    let version = env!("CARGO_PKG_VERSION");
+    let commit = env!("GIT_COMMIT");
+
+    if json {
+        println!(
+            "{{\"version\":{},\"git_commit\":{},\"solana_sdk_version\":{},\"output_formats\":{{\"leader_hints\":{},\
+             \"stakes_file\":{},\"stakes_cache\":{}}}}}",
+            json_string(version),
+            json_string(commit),
+            json_string(SOLANA_SDK_VERSION),
+            LEADER_HINTS_VERSION,
+            json_string(STAKES_FILE_VERSION),
+            json_string(STAKES_CACHE_VERSION)
+        );
+    }
+    else {
+        println!("solana_leader_schedule {} ({}, solana-sdk {})", version, commit, SOLANA_SDK_VERSION);
+    }
+}
+
+// The URL to use when --url isn't given: the json_rpc_url of the Solana CLI config file given by --config, or else
+// SOLANA_LS_URL, or else the json_rpc_url of the Solana CLI config file in its default location if there is one.  None
+// leaves get_url to choose mainnet.
+fn default_url(config : Option<String>) -> Result<Option<String>, String>
+{
+    if let Some(path) = config {
+        return Config::load(&path)
+            .map(|config| Some(config.json_rpc_url))
+            .map_err(|e| format!("ERROR: Failed to read config file {}: {}", path, e));
+    }
+
+    if let Ok(url) = std::env::var("SOLANA_LS_URL") {
+        return Ok(Some(url));
+    }
+
+    Ok(CONFIG_FILE.as_ref().and_then(|path| Config::load(path).ok()).map(|config| config.json_rpc_url))
+}
+
+fn get_url(url : Option<String>) -> Result<String, String>
+{
+    let url = url.map_or_else(
+        || DEFAULT_MAINNET_RPC_URL.to_string(),
+        |url| match url.as_str() {
+            "l" | "localhost" => DEFAULT_LOCALHOST_RPC_URL.to_string(),
+            "d" | "devnet" => DEFAULT_DEVNET_RPC_URL.to_string(),
+            "t" | "testnet" => DEFAULT_TESTNET_RPC_URL.to_string(),
+            "m" | "mainnet" | "mainnet-beta" => DEFAULT_MAINNET_RPC_URL.to_string(),
+            _ => url.clone()
+        }
+    );
+
+    validate_url(&url)?;
+
+    Ok(url)
+}
+
+// Catches the common mistakes (missing scheme, typo'd moniker, bad port) up front rather than letting them surface
+// as an obscure RPC transport error after the fact
+fn validate_url(url : &str) -> Result<(), String>
+{
+    if let Some(path) = url.strip_prefix("unix://") {
+        if cfg!(not(unix)) {
+            return Err(format!("ERROR: Invalid URL {}: unix:// URLs are only supported on Unix", url));
+        }
+        if path.is_empty() {
+            return Err(format!("ERROR: Invalid URL {}: missing socket path", url));
+        }
+        return Ok(());
+    }
+
+    let rest = url.strip_prefix("http://").or_else(|| url.strip_prefix("https://")).ok_or_else(|| {
+        format!(
+            "ERROR: Invalid URL {}: expected one of l, d, t, m, or a URL beginning with http://, https:// or unix://",
+            url
+        )
+    })?;
+
+    let authority = rest.split(&['/', '?', '#'][..]).next().unwrap_or("");
+
+    // A trailing :port is present unless the authority is a bare bracketed IPv6 address
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !authority.ends_with(']') => (host, Some(port)),
+        _ => (authority, None)
+    };
+
+    if host.is_empty() {
+        return Err(format!("ERROR: Invalid URL {}: missing host", url));
+    }
+
+    if let Some(port) = port {
+        port.parse::<u16>().map_err(|_| format!("ERROR: Invalid URL {}: bad port {}", url, port))?;
+    }
+
+    Ok(())
+}
+
+fn main()
+{
+    let args = parse_args().unwrap_or_else(|e| error_exit(ErrorCode::InvalidArguments, e));
+
+    // Comparing saved stakes needs nothing from the cluster
+    if let Some((before, after)) = &args.impact {
+        report_impact(before, after, args.output_format == OutputFormat::Diff, args.stake_unit);
+        return;
+    }
+
+    // As does computing a schedule from saved stakes
+    if let Some(path) = &args.from_stakes {
+        let (epoch, first_slot, slots_in_epoch, stakes) = read_stakes(path);
+        let leader_schedule = leader_schedule(epoch, slots_in_epoch, &stakes).unwrap_or_else(|e| predictor_error(e));
+        write_schedule(&args, epoch, first_slot, &leader_schedule, true, &[]);
+        return;
+    }
+
+    let mut profiler = Profiler::new(args.profile);
+
+    let rpc_usage = RpcUsage::default();
+
+    let predictor = LeaderSchedulePredictor::from_rpc_client(RpcClient::new_sender(
+        CountingSender::new(args.url.clone(), rpc_usage.clone(), args.retry_policy),
+        RpcClientConfig::with_commitment(CommitmentConfig::finalized())
+    ));
+
+    if args.probe {
+        report_probe(&predictor);
+        return;
+    }
+
+    let rpc_client = predictor.rpc_client();
+
+    if args.slot_time {
+        report_slot_time(rpc_client);
+        return;
+    }
+
+    if let Some(runs) = args.consistency_check {
+        report_consistency(&args, runs, &rpc_usage);
+        return;
+    }
+
+    let epoch_info = predictor.fetch_epoch_info().unwrap_or_else(|e| predictor_error(e));
+
+    profiler.phase("fetch epoch info");
+
+    if args.infer_stakes {
+        let leaders = predictor.fetch_leader_schedule(&epoch_info).unwrap_or_else(|e| predictor_error(e));
+        report_inferred_stakes(epoch_info.epoch, &leaders);
+        return;
+    }
+
+    if args.verify_cache {
+        report_cache_verification(&predictor, &epoch_info);
+        return;
+    }
+
+    // The epochs to predict.  Checked before the lengthy stake fetch, which would be wasted on an epoch that has begun
+    // or is too far off, and again after it in case the epoch advanced meanwhile
+    let check_epochs = |current_epoch : u64| {
+        let first_epoch = args.epoch.unwrap_or(current_epoch + 1);
+        if first_epoch <= current_epoch {
+            error_exit(
+                ErrorCode::InvalidArguments,
+                format!(
+                    "ERROR: Epoch {} has already begun; only epochs after {} can be predicted",
+                    first_epoch, current_epoch
+                )
+            );
+        }
+        let last_epoch = first_epoch.checked_add(args.lookahead.unwrap_or(1) - 1);
+        match last_epoch {
+            Some(last_epoch) if (last_epoch - current_epoch) <= MAX_PROJECTED_EPOCHS => first_epoch..=last_epoch,
+            _ => error_exit(
+                ErrorCode::InvalidArguments,
+                format!(
+                    "ERROR: Only epochs up to {} past the current epoch {} can be predicted",
+                    MAX_PROJECTED_EPOCHS, current_epoch
+                )
+            )
+        }
+    };
+
+    check_epochs(epoch_info.epoch);
+
+    // The stakes that determine the next epoch's schedule are the same however often they are fetched during this
+    // epoch, so those of the full scan are cached for later runs.  Runs that need the detail of each stake account, or
+    // stakes other than those of the full scan, neither use nor update the cache.
+    let stakes_cache = if args.no_cache ||
+        args.fast ||
+        args.verify.is_some() ||
+        args.diff_current ||
+        args.epoch.is_some() ||
+        args.lookahead.is_some() ||
+        args.by_withdrawer ||
+        args.lockups ||
+        args.verify_url.is_some() ||
+        args.include_current_epoch_activations
+    {
+        None
+    }
+    else {
+        cache_dir(rpc_client)
+    };
+
+    let cached_stakes = if args.refresh {
+        None
+    }
+    else {
+        stakes_cache.as_ref().and_then(|dir| {
+            let (stakes, damaged) = read_stakes_cache(dir, epoch_info.epoch + 1)?;
+            repair_stakes_cache(&predictor, &epoch_info, dir, stakes, damaged)
+        })
+    };
+
+    // Unknown features can only be found with getProgramAccounts, which the endpoints that --fast is for refuse, and
+    // stakes that were cached were computed by a run that already checked
+    if !args.fast && cached_stakes.is_none() {
+        warn_unknown_features(rpc_client);
+
+        profiler.phase("check features");
+    }
+
+    // --fast and cached stakes need no stake accounts
+    let (epoch_info, accounts) = if args.fast || cached_stakes.is_some() {
+        (epoch_info, vec![])
+    }
+    else {
+        let (epoch_info, accounts) =
+            predictor.fetch_stake_accounts_in_epoch(epoch_info).unwrap_or_else(|e| predictor_error(e));
+
+        if let Some(verify_url) = &args.verify_url {
+            verify_stake_accounts(verify_url, rpc_client.commitment(), args.retry_policy, &accounts);
+        }
+
+        profiler.phase("fetch stake accounts");
+
+        (epoch_info, accounts)
+    };
+
+    // Times are estimated relative to the moment the epoch info was current
+    let epoch_info_time = Utc::now();
+
+    let current_epoch = epoch_info.epoch;
+
+    let ms_per_slot = recent_ms_per_slot(rpc_client);
+
+    let next_epoch_start = DateTime::<Utc>::from(estimate_slot_time(
+        epoch_info.absolute_slot,
+        epoch_info_time.into(),
+        epoch_info.absolute_slot + epoch_info.slots_in_epoch.saturating_sub(epoch_info.slot_index),
+        ms_per_slot
+    ));
+
+    // Only populated when aggregating by withdraw authority: stake per (withdraw authority, vote account)
+    let mut withdrawer_stakes = HashMap::<(Pubkey, Pubkey), u128>::default();
+
+    // Only populated when reporting lockups: the counted stake accounts whose lockup is in force
+    let mut locked = Vec::<(Pubkey, StakeDelegation)>::new();
+
+    // Lockups are judged as of now, which is close enough to the cluster's clock for a lockup of any practical length
+    let clock = Clock { epoch : current_epoch, unix_timestamp : Utc::now().timestamp(), ..Clock::default() };
+
+    if let Some(tolerance) = args.verify {
+        let schedule = predictor
+            .reconstruct_stakes(&epoch_info, &accounts)
+            .and_then(|stakes| stakes.predict())
+            .unwrap_or_else(|e| predictor_error(e));
+
+        profiler.phase("compute schedule");
+
+        let cluster_leaders = predictor.fetch_leader_schedule(&epoch_info).unwrap_or_else(|e| predictor_error(e));
+
+        profiler.phase("fetch leader schedule");
+
+        report_verify(&schedule, &cluster_leaders, tolerance);
+
+        profiler.report();
+
+        if args.rpc_usage {
+            report_rpc_usage(&rpc_usage);
+        }
+
+        return;
+    }
+
+    if args.epoch.is_some() || args.lookahead.is_some() {
+        let epochs = check_epochs(current_epoch);
+
+        let projected =
+            predictor.project_stakes(&epoch_info, accounts, epochs).unwrap_or_else(|e| predictor_error(e));
+
+        profiler.phase("project stakes");
+
+        for (index, stakes) in projected.iter().enumerate() {
+            let schedule = stakes.predict().unwrap_or_else(|e| predictor_error(e));
+            if args.output_format == OutputFormat::Text {
+                if index > 0 {
+                    println!();
+                }
+                let start = DateTime::<Utc>::from(estimate_slot_time(
+                    epoch_info.absolute_slot,
+                    epoch_info_time.into(),
+                    stakes.first_slot,
+                    ms_per_slot
+                ));
+                print_epoch_start(stakes.epoch, start, stakes.first_slot, &schedule.leader_schedule);
+            }
+            write_schedule(
+                &args,
+                stakes.epoch,
+                stakes.first_slot,
+                &schedule.leader_schedule,
+                index == 0,
+                &profiler.timings()
+            );
+        }
+
+        profiler.phase("output");
+
+        profiler.report();
+
+        if args.rpc_usage {
+            report_rpc_usage(&rpc_usage);
+        }
+
+        return;
+    }
+
+    // The current epoch's schedule as the cluster has it and the stakes that it was computed from, for --diff-current
+    let current = if args.diff_current {
+        let current_stakes =
+            predictor.reconstruct_stakes(&epoch_info, &accounts).unwrap_or_else(|e| predictor_error(e));
+
+        let current_leaders = predictor.fetch_leader_schedule(&epoch_info).unwrap_or_else(|e| predictor_error(e));
+
+        profiler.phase("fetch leader schedule");
+
+        Some((current_leaders, current_stakes.identity_stakes))
+    }
+    else {
+        None
+    };
+
+    let stakes_cached = cached_stakes.is_some();
+
+    // The stakes taken on trust are worth knowing about whenever a schedule is relied on, so this is said every time
+    if args.fast {
+        eprintln!(
+            "WARNING: --fast takes stakes from getVoteAccounts as {} totals them, without checking them against the \
+             stake accounts{}",
+            args.url,
+            if args.filter_identities.is_empty() && args.filter_vote_accounts.is_empty() {
+                ""
+            }
+            else {
+                ", except for the validators given"
+            }
+        );
+    }
+
+    let stakes = if let Some(stakes) = cached_stakes {
+        Ok(stakes)
+    }
+    else if args.fast && (!args.filter_identities.is_empty() || !args.filter_vote_accounts.is_empty()) {
+        predictor.fetch_tracked_stakes(&epoch_info, &args.filter_identities, &args.filter_vote_accounts)
+    }
+    else if args.fast {
+        predictor.fetch_vote_account_stakes(&epoch_info)
+    }
+    else {
+        predictor.aggregate_stakes(&epoch_info, accounts, args.include_current_epoch_activations, |pubkey, delegation| {
+            if args.by_withdrawer {
+                *(withdrawer_stakes.entry((delegation.withdrawer, delegation.voter_pubkey)).or_insert(0)) +=
+                    delegation.stake as u128;
+            }
+            if args.lockups && delegation.lockup.is_in_force(&clock, None) {
+                locked.push((*pubkey, delegation.clone()));
+            }
+        })
+    };
+
+    let stakes = stakes.unwrap_or_else(|e| predictor_error(e));
+
+    if let Some(dir) = stakes_cache.as_ref().filter(|_| !stakes_cached) {
+        write_stakes_cache(dir, &stakes);
+    }
+
+    let Stakes {
+        epoch : next_epoch,
+        first_slot : next_epoch_first_slot,
+        slots_in_epoch,
+        vote_stakes,
+        identities,
+        identity_stakes
+    } = stakes;
+
+    profiler.phase("aggregate stakes");
+
+    if let Some(save_stakes) = &args.save_stakes {
+        write_stakes(save_stakes, next_epoch, next_epoch_first_slot, slots_in_epoch, &identity_stakes);
+    }
+
+    let leader_schedule =
+        leader_schedule(next_epoch, slots_in_epoch, &identity_stakes).unwrap_or_else(|e| predictor_error(e));
+
+    profiler.phase("compute schedule");
+
+    if args.output_format == OutputFormat::Text {
+        print_epoch_start(next_epoch, next_epoch_start, next_epoch_first_slot, &leader_schedule);
+    }
+
+    if args.explain {
+        explain(next_epoch, slots_in_epoch, &identity_stakes);
+    }
+
+    if args.threats {
+        report_threats(next_epoch, &leader_schedule, &identity_stakes, args.stake_unit);
+    }
+    else if args.by_withdrawer {
+        report_by_withdrawer(
+            next_epoch,
+            &leader_schedule,
+            &identities,
+            &identity_stakes,
+            &withdrawer_stakes,
+            args.stake_unit
+        );
+    }
+    else if let Some(identity) = &args.will_i_lead {
+        report_will_i_lead(next_epoch, identity, &leader_schedule, &identity_stakes, args.stake_unit);
+    }
+    else if !args.filter_identities.is_empty() || !args.filter_vote_accounts.is_empty() {
+        let slot_clock = args.times.map(|zone| SlotClock {
+            slot : epoch_info.absolute_slot,
+            time : epoch_info_time,
+            ms_per_slot,
+            zone
+        });
+        report_validator_slots(next_epoch, next_epoch_first_slot, &leader_schedule, &identities, &ValidatorSlotsReport {
+            identities : &args.filter_identities,
+            vote_accounts : &args.filter_vote_accounts,
+            slot_clock : slot_clock.as_ref(),
+            timeline : args.timeline
+        });
+    }
+    else if let Some(identity) = &args.checklist {
+        let slot_clock = SlotClock {
+            slot : epoch_info.absolute_slot,
+            time : epoch_info_time,
+            ms_per_slot,
+            zone : DisplayZone::Utc
+        };
+        report_checklist(rpc_client, next_epoch, next_epoch_first_slot, identity, &leader_schedule, &slot_clock);
+    }
+    else if let Some(identity) = &args.runbook {
+        let slot_clock = SlotClock {
+            slot : epoch_info.absolute_slot,
+            time : epoch_info_time,
+            ms_per_slot,
+            zone : DisplayZone::Utc
+        };
+        report_runbook(
+            &rpc_client.url(),
+            next_epoch,
+            next_epoch_first_slot,
+            identity,
+            &leader_schedule,
+            &identities,
+            &slot_clock
+        );
+    }
+    else if let Some((current_leaders, current_stakes)) = &current {
+        report_diff_current(
+            args.output_format,
+            (current_epoch, current_leaders, current_stakes),
+            (next_epoch, leader_schedule.get_slot_leaders(), &identity_stakes)
+        );
+    }
+    else if let Some(sort) = args.summary {
+        report_summary(next_epoch, sort, &leader_schedule, &identity_stakes, args.stake_unit);
+    }
+    else if args.ties {
+        report_ties(next_epoch, &leader_schedule, &identity_stakes, args.stake_unit);
+    }
+    else if args.versions {
+        report_versions(rpc_client, next_epoch, &leader_schedule);
+    }
+    else if let Some(runs) = args.monte_carlo {
+        report_monte_carlo(next_epoch, slots_in_epoch, runs, &identity_stakes);
+    }
+    else if let Some(identity) = &args.adjacency {
+        report_adjacency(next_epoch, identity, next_epoch_first_slot, &leader_schedule);
+    }
+    else if let Some(geoip) = &args.geoip {
+        println!("The leader schedule for {} will be:", next_epoch);
+
+        // Each line is the leader followed by tab-separated gossip IP, country, latitude, and longitude
+        let locations = leader_locations(rpc_client, geoip);
+        let leaders = leader_schedule.get_slot_leaders();
+        output::write_text(
+            &mut std::io::stdout().lock(),
+            leaders,
+            &output::shown_ranges(leaders.len(), args.head, args.tail),
+            |leader| format!("{}\t{}", leader, locations.get(leader).map(String::as_str).unwrap_or("-\t-\t-\t-"))
+        )
+        .unwrap_or_else(|e| error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write schedule: {}", e)));
+    }
+    else if args.resolve_names {
+        println!("The leader schedule for {} will be:", next_epoch);
+
+        // Each line is the leader followed by the tab-separated name, website, and keybase username that it publishes
+        let dir = if args.no_cache { None } else { cache_dir(rpc_client) };
+        let names = validator_names(rpc_client, dir.as_deref(), args.refresh);
+        let leaders = leader_schedule.get_slot_leaders();
+        output::write_text(
+            &mut std::io::stdout().lock(),
+            leaders,
+            &output::shown_ranges(leaders.len(), args.head, args.tail),
+            |leader| format!("{}\t{}", leader, names.get(leader).map(String::as_str).unwrap_or("-\t-\t-"))
+        )
+        .unwrap_or_else(|e| error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write schedule: {}", e)));
+    }
+    else if args.report_zero_stake {
+        report_zero_stake(rpc_client, next_epoch, &vote_stakes);
+    }
+    else if args.lockups {
+        report_lockups(next_epoch, &vote_stakes, &locked, args.stake_unit);
+    }
+    else if let Some((dir, formats)) = &args.out_dir {
+        let timings = profiler.timings();
+        write_schedule_files(&args, dir, formats, next_epoch, next_epoch_first_slot, &leader_schedule, &timings);
+    }
+    else {
+        write_schedule(&args, next_epoch, next_epoch_first_slot, &leader_schedule, true, &profiler.timings());
+    }
+
+    if let Some(export_leader_hints) = &args.export_leader_hints {
+        write_leader_hints(rpc_client, export_leader_hints, next_epoch_first_slot, &leader_schedule);
+    }
+
+    if let Some((path, start_slot_arg, end_slot_arg)) = &args.export_window {
+        let leaders = leader_schedule.get_slot_leaders();
+        let epoch_slots = next_epoch_first_slot..(next_epoch_first_slot + (leaders.len() as u64));
+        let (start_slot, end_slot) = match (
+            start_slot_arg.resolve(next_epoch, next_epoch_first_slot),
+            end_slot_arg.resolve(next_epoch, next_epoch_first_slot)
+        ) {
+            (Some(start_slot), Some(end_slot)) if start_slot <= end_slot => (start_slot, end_slot),
+            (Some(_), Some(_)) => error_exit(
+                ErrorCode::InvalidArguments,
+                format!("ERROR: Window start {} is later than its end {}", start_slot_arg, end_slot_arg)
+            ),
+            _ => error_exit(
+                ErrorCode::InvalidArguments,
+                format!(
+                    "ERROR: Window {}-{} is not within epoch {}",
+                    start_slot_arg, end_slot_arg, next_epoch
+                )
+            )
+        };
+        if !epoch_slots.contains(&start_slot) || !epoch_slots.contains(&end_slot) {
+            error_exit(
+                ErrorCode::InvalidArguments,
+                format!(
+                    "ERROR: Window {}-{} is not within epoch {} (slots {}-{})",
+                    start_slot,
+                    end_slot,
+                    next_epoch,
+                    epoch_slots.start,
+                    epoch_slots.end - 1
+                )
+            );
+        }
+        let window = ((start_slot - next_epoch_first_slot) as usize)..((end_slot - next_epoch_first_slot + 1) as usize);
+        std::fs::File::create(path)
+            .and_then(|mut file| {
+                output::write_window(&mut file, next_epoch, next_epoch_first_slot, leaders, window)
+            })
+            .unwrap_or_else(|e| {
+                error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write window to {}: {}", path, e))
+            });
+    }
+
+    if let Some(path) = &args.dump_weights {
+        let weights = schedule_stakes(&identity_stakes).unwrap_or_else(|e| predictor_error(e));
+        let seed = leader_schedule_seed(next_epoch);
+        std::fs::File::create(path)
+            .and_then(|mut file| output::write_weights(&mut file, next_epoch, slots_in_epoch, &seed, &weights))
+            .unwrap_or_else(|e| {
+                error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write weights to {}: {}", path, e))
+            });
+    }
+
+    profiler.phase("output");
+
+    profiler.report();
+
+    if args.rpc_usage {
+        report_rpc_usage(&rpc_usage);
+    }
+}
+
+impl CountingSender
+{
+    fn new(
+        url : String,
+        usage : RpcUsage,
+        retry_policy : RetryPolicy
+    ) -> Self
+    {
+        #[cfg(unix)]
+        if url.starts_with("unix://") {
+            let inner = Box::new(unix_sender::UnixSender::new(url, retry_policy.timeout));
+            return CountingSender { inner, usage, retry_policy };
+        }
+
+        let inner = Box::new(HttpSender::new_with_timeout(url, retry_policy.timeout));
+        CountingSender { inner, usage, retry_policy }
+    }
+}
+
+// Whether a failed request may succeed if sent again: it timed out or lost its connection, or the server was
+// overloaded or rate limiting.  HttpSender already retries rate limited requests a few times, honoring Retry-After;
+// those that are still rate limited after that are retried here, with longer delays.
+fn is_transient(e : &ClientError) -> bool
+{
+    match e.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(e) => {
+            e.status().is_none_or(|status| status.is_server_error() || (status.as_u16() == 429))
+        },
+        _ => false
+    }
+}
+
+// Exponential backoff with jitter, so that clients failing together don't all retry together
+fn retry_delay(retries : u32) -> Duration
+{
+    let max_ms = RETRY_BASE_DELAY_MS.saturating_mul(1 << retries.min(16)).min(RETRY_MAX_DELAY_MS);
+    Duration::from_millis(rand::thread_rng().gen_range(max_ms / 2, max_ms + 1))
+}
+
+#[async_trait::async_trait]
+impl RpcSender for CountingSender
+{
+    async fn send(
+        &self,
+        request : RpcRequest,
+        params : serde_json::Value
+    ) -> ClientResult<serde_json::Value>
+    {
+        let method = request.to_string();
+        let mut retries = 0;
+        let response = loop {
+            let response = self.inner.send(request, params.clone()).await;
+            if let Err(e) = &response {
+                if (retries < self.retry_policy.max_retries) && is_transient(e) {
+                    tokio::time::sleep(retry_delay(retries)).await;
+                    retries += 1;
+                    continue;
+                }
+            }
+            break response;
+        };
+        let mut counter = ByteCounter(0);
+        if let Ok(value) = &response {
+            // Writing to a ByteCounter cannot fail
+            serde_json::to_writer(&mut counter, value).ok();
+        }
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(method).or_insert((0, 0));
+        entry.0 += (retries + 1) as u64;
+        entry.1 += counter.0;
+        response
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats
+    {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String
+    {
+        self.inner.url()
+    }
+}
+
+impl std::io::Write for ByteCounter
+{
+    fn write(
+        &mut self,
+        buf : &[u8]
+    ) -> std::io::Result<usize>
+    {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()>
+    {
+        Ok(())
+    }
+}
+
+// Response bytes are the size of each JSON result as decoded, which is what usage-billed providers meter most
+// closely; HTTP headers, the JSON-RPC envelope and any transfer compression are not included
+fn report_rpc_usage(rpc_usage : &RpcUsage)
+{
+    let usage = rpc_usage.lock().unwrap();
+
+    eprintln!("RPC usage:");
+    for (method, (requests, bytes)) in usage.iter() {
+        eprintln!("  {:<22}{:>6} requests{:>14} bytes", method, requests, bytes);
+    }
+    eprintln!(
+        "  {:<22}{:>6} requests{:>14} bytes",
+        "total",
+        usage.values().map(|(requests, _)| requests).sum::<u64>(),
+        usage.values().map(|(_, bytes)| bytes).sum::<u64>()
+    );
+}
+
+impl std::str::FromStr for DisplayZone
+{
+    type Err = String;
+
+    // utc, local, or a fixed offset from UTC of the form +HH:MM or -HH:MM
+    fn from_str(s : &str) -> Result<Self, Self::Err>
+    {
+        let invalid = || format!("ERROR: Invalid time zone {}: expected utc, local, or an offset such as +05:30", s);
+
+        match s.to_lowercase().as_str() {
+            "utc" => return Ok(DisplayZone::Utc),
+            "local" => return Ok(DisplayZone::Local),
+            _ => ()
+        }
+
+        let sign = match s.get(0..1) {
+            Some("+") => 1,
+            Some("-") => -1,
+            _ => return Err(invalid())
+        };
+        let (hours, minutes) = s[1..].split_once(':').ok_or_else(invalid)?;
+        let hours = hours.parse::<i32>().map_err(|_| invalid())?;
+        let minutes = minutes.parse::<i32>().map_err(|_| invalid())?;
+        if minutes >= 60 {
+            return Err(invalid());
+        }
+
+        FixedOffset::east_opt(sign * ((hours * 3600) + (minutes * 60))).map(DisplayZone::Offset).ok_or_else(invalid)
+    }
+}
+
+impl StakeUnit
+{
+    // The amount alone, to be followed by name().  SOL are rounded half up, in integer arithmetic so that no amount of
+    // stake loses precision beyond the rounding asked for.
+    fn amount(
+        &self,
+        lamports : u128
+    ) -> String
+    {
+        match *self {
+            StakeUnit::Lamports => lamports.to_string(),
+            StakeUnit::Sol(decimals) => {
+                let unit = 10_u128.pow(9 - decimals);
+                let rounded = (lamports + (unit / 2)) / unit;
+                let scale = 10_u128.pow(decimals);
+                if decimals == 0 {
+                    rounded.to_string()
+                }
+                else {
+                    format!("{}.{:0width$}", rounded / scale, rounded % scale, width = decimals as usize)
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str
+    {
+        match self {
+            StakeUnit::Lamports => "lamports",
+            StakeUnit::Sol(_) => "SOL"
+        }
+    }
+
+    fn format(
+        &self,
+        lamports : u128
+    ) -> String
+    {
+        format!("{} {}", self.amount(lamports), self.name())
+    }
+}
+
+impl std::str::FromStr for SummarySort
+{
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err>
+    {
+        match s {
+            "slots" => Ok(SummarySort::Slots),
+            "stake" => Ok(SummarySort::Stake),
+            "pubkey" => Ok(SummarySort::Pubkey),
+            _ => Err(format!("ERROR: Invalid sort order {}: expected one of slots, stake, pubkey", s))
+        }
+    }
+}
+
+impl SlotClock
+{
+    fn estimate(
+        &self,
+        slot : u64
+    ) -> String
+    {
+        let time = DateTime::<Utc>::from(estimate_slot_time(self.slot, self.time.into(), slot, self.ms_per_slot));
+        match self.zone {
+            DisplayZone::Utc => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            DisplayZone::Local => time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+            DisplayZone::Offset(offset) => time.with_timezone(&offset).format("%Y-%m-%d %H:%M:%S %:z").to_string()
+        }
+    }
+}
+
+impl Profiler
+{
+    fn new(enabled : bool) -> Self
+    {
+        Profiler {
+            enabled,
+            phase_start : Instant::now(),
+            phase_allocations : ALLOCATIONS.load(Ordering::Relaxed),
+            phases : vec![]
+        }
+    }
+
+    // Ends the phase that has been running since the previous call (or since the profiler was created).  Phases are
+    // recorded even when not enabled, for the timings of JSON output.
+    fn phase(
+        &mut self,
+        name : &'static str
+    )
+    {
+        let now = Instant::now();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        self.phases.push((name, now - self.phase_start, allocations - self.phase_allocations));
+        self.phase_start = now;
+        self.phase_allocations = allocations;
+    }
+
+    // The elapsed time of each phase so far
+    fn timings(&self) -> Vec<(&'static str, Duration)>
+    {
+        self.phases.iter().map(|(name, elapsed, _)| (*name, *elapsed)).collect()
+    }
+
+    // Written to stderr so that the profile never mixes with the schedule on stdout
+    fn report(&self)
+    {
+        if !self.enabled {
+            return;
+        }
+        eprintln!("Profile:");
+        for (name, elapsed, allocations) in &self.phases {
+            eprintln!("  {:<22}{:>10.3} s{:>14} allocations", name, elapsed.as_secs_f64(), allocations);
+        }
+    }
+}
+
+// Re-fetches a random sample of the stake accounts from a second, independent RPC endpoint and compares them, as a
+// check that the primary endpoint did not serve manipulated stake data.  Accounts can legitimately change between
+// the two fetches, so differences are warned about rather than treated as fatal.
+fn verify_stake_accounts(
+    url : &str,
+    commitment : CommitmentConfig,
+    retry_policy : RetryPolicy,
+    accounts : &[(Pubkey, Account)]
+)
+{
+    let sample = accounts.choose_multiple(&mut rand::thread_rng(), VERIFY_SAMPLE_SIZE).collect::<Vec<_>>();
+
+    let pubkeys = sample.iter().map(|(pubkey, _)| *pubkey).collect::<Vec<_>>();
+
+    let verify_accounts = RpcClient::new_sender(
+        CountingSender::new(url.to_string(), RpcUsage::default(), retry_policy),
+        RpcClientConfig::with_commitment(commitment)
+    )
+        .get_multiple_accounts_with_config(&pubkeys, stake_account_config(commitment))
+        .unwrap_or_else(|e| {
+            error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch stake accounts from {}: {}", url, e))
+        })
+        .value;
+
+    let mut mismatches = 0;
+
+    for ((pubkey, account), verify_account) in sample.iter().zip(verify_accounts.iter()) {
+        let matches = verify_account.as_ref().is_some_and(|verify_account| {
+            (verify_account.lamports == account.lamports) &&
+                (verify_account.owner == account.owner) &&
+                (verify_account.data == account.data)
+        });
+        if !matches {
+            mismatches += 1;
+            eprintln!("WARNING: Stake account {} differs at {}", pubkey, url);
+        }
+    }
+
+    if mismatches > 0 {
+        eprintln!("WARNING: {} of {} sampled stake accounts differ at {}", mismatches, sample.len(), url);
+    }
+    else {
+        eprintln!("Verified {} sampled stake accounts against {}", sample.len(), url);
+    }
+}
+
+// Prints every input to the leader schedule algorithm, so that two runs which disagree can be compared
+fn explain(
+    epoch : u64,
+    slots_in_epoch : u64,
+    stakes : &HashMap<Pubkey, u128>
+)
+{
+    // Count the entries exactly as LeaderSchedule::new is given them, without the zero stakes
+    let stakes = schedule_stakes(stakes).unwrap_or_else(|e| predictor_error(e));
+
+    println!("Leader schedule inputs:");
+    println!("  Epoch: {}", epoch);
+    println!("  Seed: {}", leader_schedule_seed(epoch).iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    println!("  Slots per epoch: {}", slots_in_epoch);
+    println!("  Consecutive leader slots: {}", NUM_CONSECUTIVE_LEADER_SLOTS);
+    println!("  Weighted entries: {}", stakes.len());
+    println!("  Total weight: {}", stakes.iter().map(|(_, stake)| *stake as u128).sum::<u128>());
+    println!();
+}
+
+// Writes the plain schedule listing in the selected output format.  The CSV header is written only when csv_header is
+// set, so that the rows of several epochs can follow one header.  JSON output includes the timings of the phases of
+// the run so far.
+fn write_schedule(
+    args : &Args,
+    epoch : u64,
+    first_slot : u64,
+    leader_schedule : &LeaderSchedule,
+    csv_header : bool,
+    timings : &[(&str, Duration)]
+)
+{
+    let leaders = leader_schedule.get_slot_leaders();
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    (if csv_header && (args.output_format == OutputFormat::Csv) { output::write_csv_header(&mut out) } else { Ok(()) })
+        .and_then(|_| write_listing(&mut out, args, args.output_format, epoch, first_slot, leaders, timings))
+        .and_then(|_| std::io::Write::flush(&mut out))
+        .unwrap_or_else(|e| error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write schedule: {}", e)));
+}
+
+// Writes the plain schedule listing to dir once in each of formats, to files named for the epoch and the format, so
+// that one run produces every artifact needed
+fn write_schedule_files(
+    args : &Args,
+    dir : &str,
+    formats : &[OutputFormat],
+    epoch : u64,
+    first_slot : u64,
+    leader_schedule : &LeaderSchedule,
+    timings : &[(&str, Duration)]
+)
+{
+    std::fs::create_dir_all(dir)
+        .unwrap_or_else(|e| error_exit(ErrorCode::WriteError, format!("ERROR: Failed to create {}: {}", dir, e)));
+
+    for format in formats {
+        let path = Path::new(dir).join(format!("schedule-{}.{}", epoch, format.extension()));
+        std::fs::File::create(&path)
+            .map(std::io::BufWriter::new)
+            .and_then(|mut out| {
+                (if *format == OutputFormat::Csv { output::write_csv_header(&mut out) } else { Ok(()) })
+                    .and_then(|_| {
+                        let leaders = leader_schedule.get_slot_leaders();
+                        write_listing(&mut out, args, *format, epoch, first_slot, leaders, timings)
+                    })
+                    .and_then(|_| std::io::Write::flush(&mut out))
+            })
+            .unwrap_or_else(|e| {
+                error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write {}: {}", path.display(), e))
+            });
+        println!("Wrote the leader schedule for {} to {}", epoch, path.display());
+    }
+}
+
+// The plain schedule listing in format, of the slots that --head and --tail select; CSV rows only, without the header
+fn write_listing(
+    out : &mut impl std::io::Write,
+    args : &Args,
+    format : OutputFormat,
+    epoch : u64,
+    first_slot : u64,
+    leaders : &[Pubkey],
+    timings : &[(&str, Duration)]
+) -> std::io::Result<()>
+{
+    let ranges = output::shown_ranges(leaders.len(), args.head, args.tail);
+    match format {
+        OutputFormat::Text => {
+            writeln!(out, "The leader schedule for {} will be:", epoch)?;
+            if args.per_slot {
+                output::write_text(out, leaders, &ranges, Pubkey::to_string)
+            }
+            else {
+                output::write_text_groups(out, first_slot, leaders, &ranges)
+            }
+        },
+        OutputFormat::Json => output::write_json(out, epoch, first_slot, leaders, &ranges, timings),
+        OutputFormat::Csv => output::write_csv(out, epoch, first_slot, leaders, &ranges),
+        OutputFormat::Diff => unreachable!("--output-format diff is only accepted with --impact")
+    }
+}
+
+fn print_epoch_start(
+    epoch : u64,
+    start : DateTime<Utc>,
+    first_slot : u64,
+    leader_schedule : &LeaderSchedule
+)
+{
+    let remaining = (start - Utc::now()).num_seconds().max(0);
+
+    println!(
+        "Epoch {} starts in approximately {}d {}h {}m (at about {})",
+        epoch,
+        remaining / 86400,
+        (remaining % 86400) / 3600,
+        (remaining % 3600) / 60,
+        start.format("%Y-%m-%d %H:%M UTC")
+    );
+    // The first slot of an epoch is historically the most often skipped, as its leader is the first to run with the
+    // new epoch's state, so its leader is called out
+    if let Some(leader) = leader_schedule.get_slot_leaders().first() {
+        println!("Its first slot, {}, will be led by {}", first_slot, leader);
+    }
+    println!();
+}
+
+// Warns if the cluster has activated features that the solana-sdk this program was built against doesn't know about,
+// since such features may change stake or leader schedule computation in ways this program doesn't replicate.
+// Features that were cleaned up out of the sdk remain activated on chain, so only unknown features activated after
+// the most recently activated known feature are considered.
+fn warn_unknown_features(rpc_client : &RpcClient)
+{
+    let features = match rpc_client.get_program_accounts(&solana_sdk::feature::id()) {
+        Ok(features) => features,
+        Err(e) => {
+            eprintln!("WARNING: Unable to check cluster features: {}", e);
+            return;
+        }
+    };
+
+    let activated = features
+        .iter()
+        .filter_map(|(pubkey, account)| {
+            solana_sdk::feature::from_account(account)
+                .and_then(|feature| feature.activated_at)
+                .map(|activated_at| (pubkey, activated_at))
+        })
+        .collect::<Vec<_>>();
+
+    let newest_known = activated
+        .iter()
+        .filter(|(pubkey, _)| FEATURE_NAMES.contains_key(pubkey))
+        .map(|(_, activated_at)| *activated_at)
+        .max()
+        .unwrap_or(0);
+
+    let unknown = activated
+        .iter()
+        .filter(|(pubkey, activated_at)| (*activated_at > newest_known) && !FEATURE_NAMES.contains_key(pubkey))
+        .collect::<Vec<_>>();
+
+    if !unknown.is_empty() {
+        eprintln!(
+            "WARNING: The cluster has activated features unknown to this build; upgrade before trusting predictions:"
+        );
+        for (pubkey, activated_at) in unknown {
+            eprintln!("  {} (activated at slot {})", pubkey, activated_at);
+        }
+    }
+}
+
+// Reports the smallest sets of validators which together will lead more than one third of slots (enough to halt the
+// cluster by withholding blocks) and more than two thirds of slots.  Taking validators in order of most slots first
+// yields the smallest such sets.
+fn report_threats(
+    epoch : u64,
+    leader_schedule : &LeaderSchedule,
+    stakes : &HashMap<Pubkey, u128>,
+    unit : StakeUnit
+)
+{
+    let mut slots = slot_counts(leader_schedule.get_slot_leaders()).into_iter().collect::<Vec<_>>();
+
+    slots.sort_by(|(l_pubkey, l_slots), (r_pubkey, r_slots)| r_slots.cmp(l_slots).then(l_pubkey.cmp(r_pubkey)));
+
+    let total_slots = leader_schedule.num_slots() as u64;
+
+    for (percent, thirds) in [(33, 1), (66, 2)] {
+        let mut count = 0;
+        let mut sum = 0;
+        for (_, leader_slots) in &slots {
+            count += 1;
+            sum += leader_slots;
+            if (sum * 3) > (total_slots * thirds) {
+                break;
+            }
+        }
+
+        println!(
+            "{} validators together will lead more than {}% of slots in {} ({} of {} slots):",
+            count, percent, epoch, sum, total_slots
+        );
+
+        for (pubkey, leader_slots) in slots.iter().take(count) {
+            println!("{}  {} slots  {}", pubkey, leader_slots, unit.format(stakes.get(pubkey).copied().unwrap_or(0)));
+        }
+
+        println!();
+    }
+}
+
+// Attributes each validator's slots to the withdraw authorities of the stake delegated to it, in proportion to
+// stake, to show how much leadership is ultimately controlled by a single key
+fn report_by_withdrawer(
+    epoch : u64,
+    leader_schedule : &LeaderSchedule,
+    identities : &HashMap<Pubkey, Pubkey>,
+    identity_stakes : &HashMap<Pubkey, u128>,
+    withdrawer_stakes : &HashMap<(Pubkey, Pubkey), u128>,
+    unit : StakeUnit
+)
+{
+    let slots = slot_counts(leader_schedule.get_slot_leaders());
+
+    let mut by_withdrawer = HashMap::<Pubkey, (u128, f64)>::default();
+
+    // Each validator's slots are shared among the withdraw authorities that fund it in proportion to their stake
+    for ((withdrawer, voter), stake) in withdrawer_stakes {
+        let entry = by_withdrawer.entry(*withdrawer).or_insert((0, 0.0));
+        entry.0 += stake;
+        let identity = identities.get(voter);
+        let identity_stake = identity.and_then(|identity| identity_stakes.get(identity)).copied().unwrap_or(0);
+        let identity_slots = identity.and_then(|identity| slots.get(identity)).copied().unwrap_or(0);
+        if identity_stake > 0 {
+            entry.1 += (identity_slots as f64) * (*stake as f64) / (identity_stake as f64);
+        }
+    }
+
+    let mut by_withdrawer = by_withdrawer.into_iter().collect::<Vec<_>>();
+
+    by_withdrawer.sort_by(|(l_pubkey, (_, l_slots)), (r_pubkey, (_, r_slots))| {
+        r_slots.total_cmp(l_slots).then(l_pubkey.cmp(r_pubkey))
+    });
+
+    let total_slots = leader_schedule.num_slots() as f64;
+
+    println!("Stake and predicted slots in {} by withdraw authority:", epoch);
+
+    for (withdrawer, (stake, withdrawer_slots)) in by_withdrawer {
+        println!(
+            "{}  {}  {:.1} slots  {:.3}%",
+            withdrawer,
+            unit.format(stake),
+            withdrawer_slots,
+            (withdrawer_slots * 100.0) / total_slots
+        );
+    }
+}
+
+// Tells a validator whether it will be leader in the epoch, and if not, roughly how much more stake it would need
+fn report_will_i_lead(
+    epoch : u64,
+    identity : &Pubkey,
+    leader_schedule : &LeaderSchedule,
+    stakes : &HashMap<Pubkey, u128>,
+    unit : StakeUnit
+)
+{
+    let stake = stakes.get(identity).copied().unwrap_or(0);
+    let identity_slots = slot_counts(leader_schedule.get_slot_leaders()).get(identity).copied().unwrap_or(0);
+
+    if identity_slots > 0 {
+        println!("{} will lead {} slots in {} with {} of stake", identity, identity_slots, epoch, unit.format(stake));
+        return;
+    }
+
+    // Expected slots are num_slots * stake / total_stake.  Adding x lamports to both stake and total stake, one
+    // expected slot needs (stake + x) * num_slots = total_stake + x, i.e. x = (total_stake - stake * num_slots) /
+    // (num_slots - 1).
+    let total_stake = stakes.values().sum::<u128>();
+    let num_slots = leader_schedule.num_slots() as u128;
+    let needed = (total_stake.saturating_sub(stake * num_slots) + (num_slots - 2)) / (num_slots - 1);
+
+    println!("{} will not lead any slots in {} with {} of stake", identity, epoch, unit.format(stake));
+    println!(
+        "About {} more of activated stake would give one expected slot (slots are assigned in groups of {})",
+        unit.format(needed),
+        NUM_CONSECUTIVE_LEADER_SLOTS
+    );
+}
+
+// For each of a validator's leader blocks, shows who leads immediately before and after it; the preceding leader
+// failing to produce its last blocks is the most common cause of a skipped first slot
+fn report_adjacency(
+    epoch : u64,
+    identity : &Pubkey,
+    first_slot : u64,
+    leader_schedule : &LeaderSchedule
+)
+{
+    let leaders = leader_schedule.get_slot_leaders();
+
+    println!("Leaders adjacent to {} in {} (first slot, preceding leader, following leader):", identity, epoch);
+
+    for group in leader_groups(leaders, identity) {
+        let describe = |leader : Option<&Pubkey>| leader.map_or_else(|| "-".to_string(), Pubkey::to_string);
+        println!(
+            "{}  {}  {}",
+            first_slot + (group.start as u64),
+            describe(group.start.checked_sub(1).map(|before| &leaders[before])),
+            describe(leaders.get(group.end))
+        );
+    }
+}
+
+// Lists each of the given validators' groups of consecutive leader slots, and the number of slots between one group
+// and the next, which is the time available for restarts and other maintenance.  Vote accounts are shown as the
+// identity that votes with them.
+fn report_validator_slots(
+    epoch : u64,
+    first_slot : u64,
+    leader_schedule : &LeaderSchedule,
+    vote_identities : &HashMap<Pubkey, Pubkey>,
+    report : &ValidatorSlotsReport
+)
+{
+    let ValidatorSlotsReport { identities, vote_accounts, slot_clock, timeline } = *report;
+
+    let leaders = leader_schedule.get_slot_leaders();
+
+    for vote_account in vote_accounts {
+        if !vote_identities.contains_key(vote_account) {
+            println!("Vote account {} has no stake in {}, and so no leader slots", vote_account, epoch);
+        }
+    }
+
+    // A validator may have been given both by identity and by vote account
+    let mut shown = Vec::<Pubkey>::new();
+    let vote_account_identities = vote_accounts.iter().filter_map(|vote_account| vote_identities.get(vote_account));
+    for identity in identities.iter().chain(vote_account_identities) {
+        if !shown.contains(identity) {
+            shown.push(*identity);
+        }
+    }
+
+    for identity in shown {
+        let groups = leader_groups(leaders, &identity);
+        println!(
+            "{} leads {} slots in {} in {} groups (first slot, last slot, slots since previous group{}):",
+            identity,
+            groups.iter().map(|group| group.len()).sum::<usize>(),
+            epoch,
+            groups.len(),
+            if slot_clock.is_some() { ", estimated start time" } else { "" }
+        );
+        if timeline {
+            println!("|{}|", timeline_row(leaders, &identity));
+        }
+        let mut previous_end = None;
+        for group in groups {
+            let group_first_slot = first_slot + (group.start as u64);
+            println!(
+                "{}  {}  {}{}",
+                group_first_slot,
+                first_slot + ((group.end - 1) as u64),
+                previous_end.map_or_else(|| "-".to_string(), |end : usize| (group.start - end).to_string()),
+                slot_clock.map_or_else(String::new, |slot_clock| format!("  {}", slot_clock.estimate(group_first_slot)))
+            );
+            previous_end = Some(group.end);
+        }
+    }
+}
+
+// The epoch drawn as a row of TIMELINE_WIDTH cells from its first slot to its last, each a block if identity leads any
+// of the cell's slots and a dot otherwise
+fn timeline_row(
+    leaders : &[Pubkey],
+    identity : &Pubkey
+) -> String
+{
+    let cell_slots = leaders.len().div_ceil(TIMELINE_WIDTH).max(1);
+
+    leaders
+        .chunks(cell_slots)
+        .map(|cell| if cell.contains(identity) { '\u{2588}' } else { '\u{00b7}' })
+        .collect()
+}
+
+// The slots and seconds covered by the cluster's performance samples of roughly the last hour, or None if the RPC node
+// has no samples or won't serve them
+fn recent_slot_samples(rpc_client : &RpcClient) -> Option<(u64, u64)>
+{
+    let samples = rpc_client.get_recent_performance_samples(Some(60)).ok()?;
+
+    let slots = samples.iter().map(|sample| sample.num_slots).sum::<u64>();
+    let secs = samples.iter().map(|sample| sample.sample_period_secs as u64).sum::<u64>();
+
+    if slots == 0 {
+        None
+    }
+    else {
+        Some((slots, secs))
+    }
+}
+
+// The cluster's average slot time over roughly the last hour, or the nominal slot time if it can't be measured.  All
+// estimates of when slots will occur use this, as the actual slot time is often well above nominal.
+fn recent_ms_per_slot(rpc_client : &RpcClient) -> f64
+{
+    recent_slot_samples(rpc_client)
+        .map_or(DEFAULT_MS_PER_SLOT as f64, |(slots, secs)| ((secs * 1000) as f64) / (slots as f64))
+}
+
+// The measured slot time that estimates use, against the nominal
+fn report_slot_time(rpc_client : &RpcClient)
+{
+    match recent_slot_samples(rpc_client) {
+        Some((slots, secs)) => println!(
+            "Slot time at {}: {:.1} ms over the last {} slots ({}m), nominal {} ms",
+            rpc_client.url(),
+            ((secs * 1000) as f64) / (slots as f64),
+            slots,
+            secs / 60,
+            DEFAULT_MS_PER_SLOT
+        ),
+        None => println!(
+            "Slot time at {}: no performance samples available, the nominal {} ms is assumed",
+            rpc_client.url(),
+            DEFAULT_MS_PER_SLOT
+        )
+    }
+}
+
+// The index ranges of the runs of consecutive slots led by identity
+fn leader_groups(
+    leaders : &[Pubkey],
+    identity : &Pubkey
+) -> Vec<std::ops::Range<usize>>
+{
+    let mut groups = vec![];
+
+    let mut index = 0;
+    while index < leaders.len() {
+        if leaders[index] != *identity {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while (index < leaders.len()) && (leaders[index] == *identity) {
+            index += 1;
+        }
+        groups.push(start..index);
+    }
+
+    groups
+}
+
+// Stakes files hold STAKES_FILE_VERSION, the epoch that the stakes are for, its length in slots and its first slot on
+// the first line, followed by one line per validator of its identity pubkey and stake in lamports, each separated by a
+// space
+fn write_stakes(
+    path : &str,
+    epoch : u64,
+    first_slot : u64,
+    slots_in_epoch : u64,
+    stakes : &HashMap<Pubkey, u128>
+)
+{
+    let mut sorted = stakes.iter().collect::<Vec<_>>();
+
+    sorted.sort_by_key(|(pubkey, _)| *pubkey);
+
+    let mut contents = format!("{} {} {} {}\n", STAKES_FILE_VERSION, epoch, slots_in_epoch, first_slot);
+    for (pubkey, stake) in sorted {
+        contents.push_str(&format!("{} {}\n", pubkey, stake));
+    }
+
+    std::fs::write(path, contents).unwrap_or_else(|e| {
+        error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write stakes to {}: {}", path, e))
+    });
+}
+
+// Returns the epoch, its first slot, its length in slots, and the stakes
+fn read_stakes(path : &str) -> (u64, u64, u64, HashMap<Pubkey, u128>)
+{
+    let contents = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| error_exit(ErrorCode::ReadError, format!("ERROR: Failed to read stakes {}: {}", path, e)));
+
+    let mut lines = contents.lines();
+
+    let header = lines.next().unwrap_or("");
+    let mut header_fields = header.split_whitespace();
+    if header_fields.next() != Some(STAKES_FILE_VERSION) {
+        error_exit(
+            ErrorCode::ReadError,
+            format!("ERROR: Stakes file {} is not of the {} format", path, STAKES_FILE_VERSION)
+        );
+    }
+    let mut header_fields = header_fields.map(|field| {
+        field.parse::<u64>().unwrap_or_else(|_| invalid_stakes_line(path, header))
+    });
+    let mut header_field = || header_fields.next().unwrap_or_else(|| invalid_stakes_line(path, header));
+    let epoch = header_field();
+    let slots_in_epoch = header_field();
+    let first_slot = header_field();
+    if header_fields.next().is_some() ||
+        (slots_in_epoch == 0) ||
+        first_slot.checked_add(slots_in_epoch).is_none()
+    {
+        invalid_stakes_line(path, header);
+    }
+
+    let stakes = lines
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (pubkey, stake) = line.trim().split_once(' ').unwrap_or_else(|| invalid_stakes_line(path, line));
+            (
+                pubkey.parse::<Pubkey>().unwrap_or_else(|_| invalid_stakes_line(path, line)),
+                stake.trim().parse::<u128>().unwrap_or_else(|_| invalid_stakes_line(path, line))
+            )
+        })
+        .collect::<HashMap<Pubkey, u128>>();
+
+    if stakes.values().all(|stake| *stake == 0) {
+        error_exit(ErrorCode::ReadError, format!("ERROR: Stakes file {} has no stake", path));
+    }
+
+    (epoch, first_slot, slots_in_epoch, stakes)
+}
+
+// The directory of cached stakes and validator info of the cluster at rpc_client: solana_leader_schedule/<genesis
+// hash> in the platform's cache directory ($XDG_CACHE_HOME or ~/.cache, ~/Library/Caches, or %LOCALAPPDATA%), so that
+// runs against different endpoints of the same cluster share it.  None if there is no cache directory or the genesis
+// hash can't be fetched.
+fn cache_dir(rpc_client : &RpcClient) -> Option<PathBuf>
+{
+    let base = dirs_next::cache_dir()?;
+
+    let genesis_hash = rpc_client.get_genesis_hash().ok()?;
+
+    Some(base.join("solana_leader_schedule").join(genesis_hash.to_string()))
+}
+
+fn stakes_cache_path(
+    dir : &Path,
+    epoch : u64
+) -> PathBuf
+{
+    dir.join(format!("epoch-{}.stakes", epoch))
+}
+
+// Cached stakes files are named for the epoch that they are the stakes of, and hold STAKES_CACHE_VERSION and the
+// epoch's length in slots and first slot on the first line, followed by one line per vote account of its pubkey, its
+// identity (or - if it has none), and its stake in lamports.  The vote account lines are in pubkey order, in chunks of
+// STAKES_CACHE_CHUNK_SIZE, each preceded by a line of "chunk", the first and last vote account pubkeys of the chunk,
+// and the SHA-256 of its lines.  Returns the stakes of every intact chunk, with the first and last vote account
+// pubkeys of each damaged chunk so that just those can be refetched.  Any file that can't be read, is of another
+// version, or has no intact chunk is treated as absent.
+fn read_stakes_cache(
+    dir : &Path,
+    epoch : u64
+) -> Option<(Stakes, Vec<(Pubkey, Pubkey)>)>
+{
+    let contents = std::fs::read_to_string(stakes_cache_path(dir, epoch)).ok()?;
+
+    let mut lines = contents.lines();
+
+    let mut header = lines.next()?.split_whitespace();
+    if header.next()? != STAKES_CACHE_VERSION {
+        return None;
+    }
+    let slots_in_epoch = header.next()?.parse::<u64>().ok()?;
+    let first_slot = header.next()?.parse::<u64>().ok()?;
+    if header.next().is_some() {
+        return None;
+    }
+
+    // Chunks are found by their header lines rather than by counting, so that lines lost or gained damage only the
+    // chunk that they were in
+    let mut chunks = Vec::<(&str, Vec<&str>)>::new();
+    for line in lines {
+        match line.strip_prefix("chunk ") {
+            Some(chunk_header) => chunks.push((chunk_header, vec![])),
+            None => chunks.last_mut()?.1.push(line)
+        }
+    }
+
+    let mut vote_stakes = HashMap::<Pubkey, u128>::default();
+    let mut identities = HashMap::<Pubkey, Pubkey>::default();
+    let mut damaged = vec![];
+
+    for (chunk_header, chunk_lines) in &chunks {
+        let mut fields = chunk_header.split_whitespace();
+        let first = fields.next()?.parse::<Pubkey>().ok()?;
+        let last = fields.next()?.parse::<Pubkey>().ok()?;
+        let checksum = fields.next()?;
+        if fields.next().is_some() {
+            return None;
+        }
+
+        let intact = (stakes_cache_checksum(chunk_lines) == checksum)
+            .then(|| read_stakes_cache_chunk(chunk_lines))
+            .flatten()
+            .filter(|chunk| chunk.iter().all(|(vote_pubkey, _, _)| (first <= *vote_pubkey) && (*vote_pubkey <= last)));
+
+        match intact {
+            Some(chunk) => {
+                for (vote_pubkey, identity, stake) in chunk {
+                    vote_stakes.insert(vote_pubkey, stake);
+                    if let Some(identity) = identity {
+                        identities.insert(vote_pubkey, identity);
+                    }
+                }
+            },
+            None => damaged.push((first, last))
+        }
+    }
+
+    if vote_stakes.is_empty() {
+        return None;
+    }
+
+    Some((Stakes::new(epoch, first_slot, slots_in_epoch, vote_stakes, identities), damaged))
+}
+
+// The vote account pubkey, identity and stake of each line of a cached stakes chunk, or None if any line is invalid
+fn read_stakes_cache_chunk(lines : &[&str]) -> Option<Vec<(Pubkey, Option<Pubkey>, u128)>>
+{
+    lines
+        .iter()
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let vote_pubkey = fields.next()?.parse::<Pubkey>().ok()?;
+            let identity = match fields.next()? {
+                "-" => None,
+                identity => Some(identity.parse::<Pubkey>().ok()?)
+            };
+            let stake = fields.next()?.parse::<u128>().ok()?;
+            fields.next().is_none().then_some((vote_pubkey, identity, stake))
+        })
+        .collect()
+}
+
+fn stakes_cache_checksum(lines : &[&str]) -> String
+{
+    hashv(&lines.iter().flat_map(|line| [line.as_bytes(), &b"\n"[..]]).collect::<Vec<_>>()).to_string()
+}
+
+// Refetches the stake of the vote accounts of each damaged chunk of cached stakes from their own stake accounts, and
+// caches the repaired stakes.  Vote accounts are found by getVoteAccounts, so stake delegated to vote accounts that
+// don't exist, which has no identity to lead, is lost from the repaired chunks.  None if the stakes can't be fetched,
+// for the full fetch to be done instead.
+fn repair_stakes_cache(
+    predictor : &LeaderSchedulePredictor,
+    epoch_info : &EpochInfo,
+    dir : &Path,
+    stakes : Stakes,
+    damaged : Vec<(Pubkey, Pubkey)>
+) -> Option<Stakes>
+{
+    if damaged.is_empty() {
+        return Some(stakes);
+    }
+
+    eprintln!("WARNING: Refetching {} damaged chunks of the stakes cache in {}", damaged.len(), dir.display());
+
+    let vote_accounts = predictor
+        .rpc_client()
+        .get_vote_accounts()
+        .map_err(|e| eprintln!("WARNING: Failed to fetch vote accounts: {}", e))
+        .ok()?;
+
+    let vote_pubkeys = vote_accounts
+        .current
+        .iter()
+        .chain(vote_accounts.delinquent.iter())
+        .filter_map(|vote_account| vote_account.vote_pubkey.parse::<Pubkey>().ok())
+        .filter(|vote_pubkey| damaged.iter().any(|(first, last)| (first <= vote_pubkey) && (vote_pubkey <= last)))
+        .collect::<Vec<_>>();
+
+    let (repaired_stakes, repaired_identities) = predictor
+        .fetch_delegated_stakes(epoch_info, &vote_pubkeys)
+        .map_err(|e| eprintln!("WARNING: Failed to refetch damaged stakes: {}", e))
+        .ok()?;
+
+    let Stakes { epoch, first_slot, slots_in_epoch, mut vote_stakes, mut identities, .. } = stakes;
+    vote_stakes.extend(repaired_stakes);
+    identities.extend(repaired_identities);
+
+    let stakes = Stakes::new(epoch, first_slot, slots_in_epoch, vote_stakes, identities);
+
+    write_stakes_cache(dir, &stakes);
+
+    Some(stakes)
+}
+
+// A failure to cache only costs later runs the fetch, so it is warned about rather than fatal.  Only the stakes of the
+// next epoch are ever read back, so those cached for earlier epochs are removed.
+fn write_stakes_cache(
+    dir : &Path,
+    stakes : &Stakes
+)
+{
+    let mut sorted = stakes.vote_stakes.iter().collect::<Vec<_>>();
+
+    sorted.sort_by_key(|(pubkey, _)| *pubkey);
+
+    let mut contents = format!("{} {} {}\n", STAKES_CACHE_VERSION, stakes.slots_in_epoch, stakes.first_slot);
+    for chunk in sorted.chunks(STAKES_CACHE_CHUNK_SIZE) {
+        let lines = chunk
+            .iter()
+            .map(|(vote_pubkey, stake)| {
+                let identity = stakes.identities.get(vote_pubkey).map_or_else(|| "-".to_string(), Pubkey::to_string);
+                format!("{} {} {}", vote_pubkey, identity, stake)
+            })
+            .collect::<Vec<_>>();
+        let lines = lines.iter().map(String::as_str).collect::<Vec<_>>();
+        contents.push_str(&format!(
+            "chunk {} {} {}\n",
+            chunk[0].0,
+            chunk[chunk.len() - 1].0,
+            stakes_cache_checksum(&lines)
+        ));
+        for line in lines {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+    }
+
+    // Written under another name and renamed into place, so that a concurrent run never reads a partial file
+    let path = stakes_cache_path(dir, stakes.epoch);
+    let partial = dir.join(format!("epoch-{}.stakes.{}", stakes.epoch, std::process::id()));
+
+    if let Err(e) = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&partial, contents))
+        .and_then(|_| std::fs::rename(&partial, &path))
+    {
+        eprintln!("WARNING: Failed to cache stakes in {}: {}", path.display(), e);
+        return;
+    }
+
+    prune_stakes_cache(dir, stakes.epoch);
+}
+
+// Removes the cached stakes of epochs before epoch.  Files that can't be removed are left for a later run to retry.
+fn prune_stakes_cache(
+    dir : &Path,
+    epoch : u64
+)
+{
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+
+    for entry in entries.flatten() {
+        let stale = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("epoch-"))
+            .and_then(|name| name.strip_suffix(".stakes"))
+            .and_then(|cached_epoch| cached_epoch.parse::<u64>().ok())
+            .is_some_and(|cached_epoch| cached_epoch < epoch);
+        if stale {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn invalid_stakes_line(
+    path : &str,
+    line : &str
+) -> !
+{
+    error_exit(ErrorCode::ReadError, format!("ERROR: Invalid line in stakes file {}: {}", path, line))
+}
+
+// Computes the schedule for two sets of saved stakes and shows how each validator's change in stake changed its
+// slots.  Both schedules use the epoch of the after file so that the seed is the same and only stake differs.
+fn report_impact(
+    before_path : &str,
+    after_path : &str,
+    diff : bool,
+    unit : StakeUnit
+)
+{
+    let (_, _, _, before) = read_stakes(before_path);
+    let (epoch, first_slot, slots_in_epoch, after) = read_stakes(after_path);
+
+    let schedule = |stakes : &HashMap<Pubkey, u128>| {
+        leader_schedule(epoch, slots_in_epoch, stakes).unwrap_or_else(|e| predictor_error(e))
+    };
+
+    let before_schedule = schedule(&before);
+    let after_schedule = schedule(&after);
+
+    if diff {
+        let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+        output::write_diff(
+            &mut out,
+            before_path,
+            after_path,
+            first_slot,
+            before_schedule.get_slot_leaders(),
+            after_schedule.get_slot_leaders()
+        )
+        .and_then(|_| std::io::Write::flush(&mut out))
+        .unwrap_or_else(|e| error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write diff: {}", e)));
+        return;
+    }
+
+    let before_slots = slot_counts(before_schedule.get_slot_leaders());
+    let after_slots = slot_counts(after_schedule.get_slot_leaders());
+
+    let mut changes = before
+        .keys()
+        .chain(after.keys())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .map(|pubkey| {
+            (
+                *pubkey,
+                before.get(pubkey).copied().unwrap_or(0),
+                after.get(pubkey).copied().unwrap_or(0),
+                before_slots.get(pubkey).copied().unwrap_or(0),
+                after_slots.get(pubkey).copied().unwrap_or(0)
+            )
+        })
+        .filter(|(_, before_stake, after_stake, before_slots, after_slots)| {
+            (before_stake != after_stake) || (before_slots != after_slots)
+        })
+        .collect::<Vec<_>>();
+
+    let slot_change = |before_slots : u64, after_slots : u64| (after_slots as i64) - (before_slots as i64);
+
+    changes.sort_by(|(l_pubkey, _, _, l_before, l_after), (r_pubkey, _, _, r_before, r_after)| {
+        slot_change(*r_before, *r_after).abs().cmp(&slot_change(*l_before, *l_after).abs()).then(l_pubkey.cmp(r_pubkey))
+    });
+
+    println!("Change in stake and slots in {} from {} to {}:", epoch, before_path, after_path);
+
+    for (pubkey, before_stake, after_stake, before_slots, after_slots) in changes {
+        println!(
+            "{}  {} -> {} {}  {} -> {} slots ({:+})",
+            pubkey,
+            unit.amount(before_stake),
+            unit.amount(after_stake),
+            unit.name(),
+            before_slots,
+            after_slots,
+            slot_change(before_slots, after_slots)
+        );
+    }
+}
+
+// Computes the schedule with the epoch's stakes under many random seeds, to show how much a validator's slot count
+// varies around its stake-proportional share purely from the randomness of the schedule
+fn report_monte_carlo(
+    epoch : u64,
+    slots_in_epoch : u64,
+    runs : usize,
+    stakes : &HashMap<Pubkey, u128>
+)
+{
+    let sorted_stakes = schedule_stakes(stakes).unwrap_or_else(|e| predictor_error(e));
+
+    let mut samples = HashMap::<Pubkey, Vec<u64>>::default();
+
+    for _ in 0..runs {
+        let schedule = LeaderSchedule::new(
+            &sorted_stakes,
+            rand::random::<[u8; 32]>(),
+            slots_in_epoch,
+            NUM_CONSECUTIVE_LEADER_SLOTS
+        );
+        let slots = slot_counts(schedule.get_slot_leaders());
+        for (pubkey, _) in &sorted_stakes {
+            samples.entry(*pubkey).or_default().push(slots.get(pubkey).copied().unwrap_or(0));
+        }
+    }
+
+    let total_stake = stakes.values().sum::<u128>() as f64;
+
+    let mut by_stake = sorted_stakes.clone();
+
+    by_stake.sort_by(|(l_pubkey, l_stake), (r_pubkey, r_stake)| r_stake.cmp(l_stake).then(l_pubkey.cmp(r_pubkey)));
+
+    println!("Slot counts in {} over {} random seeds (expected, mean, standard deviation, min, max):", epoch, runs);
+
+    for (pubkey, stake) in by_stake {
+        let samples = &samples[&pubkey];
+        let mean = (samples.iter().sum::<u64>() as f64) / (runs as f64);
+        let variance = samples.iter().map(|slots| ((*slots as f64) - mean).powi(2)).sum::<f64>() / (runs as f64);
+        println!(
+            "{}  {:.1}  {:.1}  {:.1}  {}  {}",
+            pubkey,
+            ((stake as f64) * (slots_in_epoch as f64)) / total_stake,
+            mean,
+            variance.sqrt(),
+            samples.iter().min().unwrap(),
+            samples.iter().max().unwrap()
+        );
+    }
+}
+
+// Diffs the schedule computed for the current epoch against the cluster's, and fails if more than tolerance slots'
+// leaders differ.  Stake accounts closed since the previous epoch began are missing from the computed schedule's
+// stakes, so a few mismatched slots can be expected on a busy cluster and allowed with --verify-tolerance; a mismatch
+// in most slots means the algorithm has diverged.
+fn report_verify(
+    schedule : &PredictedSchedule,
+    cluster_leaders : &[Pubkey],
+    tolerance : usize
+)
+{
+    let leaders = schedule.leaders();
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    output::write_diff(&mut out, "predicted", "getLeaderSchedule", schedule.first_slot, leaders, cluster_leaders)
+        .and_then(|_| std::io::Write::flush(&mut out))
+        .unwrap_or_else(|e| error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write diff: {}", e)));
+    drop(out);
+
+    let mismatched = leaders.iter().zip(cluster_leaders).filter(|(leader, cluster)| leader != cluster).count();
+
+    if mismatched > tolerance {
+        error_exit(
+            ErrorCode::ScheduleMismatch,
+            format!(
+                "ERROR: {} of {} slots of epoch {} differ from getLeaderSchedule, more than the {} allowed",
+                mismatched,
+                leaders.len(),
+                schedule.epoch,
+                tolerance
+            )
+        );
+    }
+
+    if mismatched > 0 {
+        println!(
+            "{} of {} slots of epoch {} differ from getLeaderSchedule, within the {} allowed",
+            mismatched,
+            leaders.len(),
+            schedule.epoch,
+            tolerance
+        );
+    }
+    else {
+        println!("All {} slots of epoch {} match getLeaderSchedule", leaders.len(), schedule.epoch);
+    }
+}
+
+// Fetches the stakes and computes the next epoch's schedule runs times, cycling through the endpoints, and fails unless
+// every run produced the same schedule.  Stake accounts that change during the epoch don't change the effective stake,
+// so differing schedules mean an endpoint served inconsistent state, or the epoch advanced between runs.
+fn report_consistency(
+    args : &Args,
+    runs : usize,
+    rpc_usage : &RpcUsage
+)
+{
+    let urls = std::iter::once(&args.url).chain(args.consistency_urls.iter()).collect::<Vec<_>>();
+
+    let mut schedules = BTreeMap::<(u64, String), usize>::new();
+
+    for run in 0..runs {
+        let url = urls[run % urls.len()];
+        let start = Instant::now();
+        let predictor = LeaderSchedulePredictor::from_rpc_client(RpcClient::new_sender(
+            CountingSender::new(url.clone(), rpc_usage.clone(), args.retry_policy),
+            RpcClientConfig::with_commitment(CommitmentConfig::finalized())
+        ));
+        let schedule =
+            predictor.fetch_stakes().and_then(|stakes| stakes.predict()).unwrap_or_else(|e| predictor_error(e));
+        let hash = hashv(&schedule.leaders().iter().map(|leader| leader.as_ref()).collect::<Vec<_>>()).to_string();
+        println!(
+            "Run {}  {}  epoch {}  schedule {}  {:.1}s",
+            run + 1,
+            url,
+            schedule.epoch,
+            hash,
+            start.elapsed().as_secs_f64()
+        );
+        *(schedules.entry((schedule.epoch, hash)).or_insert(0)) += 1;
+    }
+
+    if schedules.len() > 1 {
+        error_exit(
+            ErrorCode::ScheduleMismatch,
+            format!("ERROR: {} runs produced {} different schedules", runs, schedules.len())
+        );
+    }
+
+    println!("All {} runs produced the same schedule", runs);
+}
+
+// Lists whether each RPC method that prediction requires works at the endpoint, and fails with the first error if
+// any doesn't
+fn report_probe(predictor : &LeaderSchedulePredictor)
+{
+    let results = predictor.probe();
+
+    println!("RPC methods at {}:", predictor.rpc_client().url());
+
+    for (method, result) in &results {
+        match result {
+            Ok(()) => println!("{}  ok", method),
+            Err(e) => println!("{}  FAILED: {}", method, e)
+        }
+    }
+
+    if let Some((_, Err(e))) = results.into_iter().find(|(_, result)| result.is_err()) {
+        predictor_error(e);
+    }
+}
+
+// Everything a validator operator should check before the epoch begins, in one report.  Warnings are for conditions
+// that would cost the validator its slots or its delegators' rewards.
+fn report_checklist(
+    rpc_client : &RpcClient,
+    epoch : u64,
+    first_slot : u64,
+    identity : &Pubkey,
+    leader_schedule : &LeaderSchedule,
+    slot_clock : &SlotClock
+)
+{
+    let groups = leader_groups(leader_schedule.get_slot_leaders(), identity);
+
+    let node = rpc_client
+        .get_cluster_nodes()
+        .unwrap_or_else(|e| error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch cluster nodes: {}", e)))
+        .into_iter()
+        .find(|node| node.pubkey == identity.to_string());
+
+    let vote_accounts = rpc_client
+        .get_vote_accounts()
+        .unwrap_or_else(|e| error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch vote accounts: {}", e)));
+
+    let identity_string = identity.to_string();
+
+    let current = vote_accounts.current.iter().filter(|vote_account| vote_account.node_pubkey == identity_string);
+    let delinquent = vote_accounts.delinquent.iter().filter(|vote_account| vote_account.node_pubkey == identity_string);
+
+    let mut warnings = vec![];
+
+    println!("Readiness checklist for {} in {}:", identity, epoch);
+
+    println!("  Leader slots: {} in {} groups", groups.iter().map(|group| group.len()).sum::<usize>(), groups.len());
+
+    if let Some(group) = groups.first() {
+        let slot = first_slot + (group.start as u64);
+        println!("  First leader slot: {} at about {}", slot, slot_clock.estimate(slot));
+    }
+    else {
+        warnings.push("will lead no slots".to_string());
+    }
+
+    if let Some(group) = groups.iter().max_by(|l, r| l.len().cmp(&r.len()).then(r.start.cmp(&l.start))) {
+        println!(
+            "  Longest run of consecutive leader slots: {} slots from slot {}",
+            group.len(),
+            first_slot + (group.start as u64)
+        );
+    }
+
+    match node.as_ref().and_then(|node| node.version.as_ref()) {
+        Some(version) => println!("  Version in gossip: {}", version),
+        None => warnings.push("is not in gossip".to_string())
+    }
+
+    for vote_account in current.clone() {
+        println!("  Vote account: {} (commission {}%)", vote_account.vote_pubkey, vote_account.commission);
+    }
+
+    for vote_account in delinquent {
+        println!("  Vote account: {} (commission {}%)", vote_account.vote_pubkey, vote_account.commission);
+        warnings.push(format!("vote account {} is delinquent", vote_account.vote_pubkey));
+    }
+
+    if current.count() == 0 {
+        warnings.push("has no vote account that is voting".to_string());
+    }
+
+    for warning in warnings {
+        println!("  WARNING: {} {}", identity, warning);
+    }
+}
+
+// A markdown document of the validator's leader slot groups in the epoch, for pasting into an on-call wiki: links to
+// the explorer pages to watch, a checklist to work through before the first group, and a table of the groups
+fn report_runbook(
+    url : &str,
+    epoch : u64,
+    first_slot : u64,
+    identity : &Pubkey,
+    leader_schedule : &LeaderSchedule,
+    identities : &HashMap<Pubkey, Pubkey>,
+    slot_clock : &SlotClock
+)
+{
+    let groups = leader_groups(leader_schedule.get_slot_leaders(), identity);
+
+    let cluster = explorer_cluster(url);
+
+    let mut vote_accounts = identities
+        .iter()
+        .filter(|(_, vote_identity)| *vote_identity == identity)
+        .map(|(vote_account, _)| *vote_account)
+        .collect::<Vec<_>>();
+    vote_accounts.sort_unstable();
+
+    println!("# Leader runbook for {} in epoch {}", identity, epoch);
+    println!();
+    println!(
+        "{} leader slots in {} groups.  Times are estimated at {:.0} ms per slot.",
+        groups.iter().map(|group| group.len()).sum::<usize>(),
+        groups.len(),
+        slot_clock.ms_per_slot
+    );
+    println!();
+    println!("## Monitoring");
+    println!();
+    println!("- Identity: [{}](https://explorer.solana.com/address/{}{})", identity, identity, cluster);
+    for vote_account in &vote_accounts {
+        println!("- Vote account: [{}](https://explorer.solana.com/address/{}{})", vote_account, vote_account, cluster);
+    }
+    println!();
+    println!("## Before the first leader slot");
+    println!();
+    println!("- [ ] The validator is caught up: `solana catchup {} --url {}`", identity, url);
+    println!("- [ ] The vote account is voting and not delinquent: `solana validators --url {}`", url);
+    println!("- [ ] The identity account holds enough SOL for vote fees: `solana balance {} --url {}`", identity, url);
+    println!("- [ ] No restart, upgrade or identity change is planned during a leader slot group");
+    println!();
+    println!("## Leader slot groups");
+    println!();
+
+    if groups.is_empty() {
+        println!("None.");
+        return;
+    }
+
+    println!("| First slot | Last slot | Estimated start | Block |");
+    println!("|---|---|---|---|");
+
+    for group in &groups {
+        let group_first_slot = first_slot + (group.start as u64);
+        println!(
+            "| {} | {} | {} | [explorer](https://explorer.solana.com/block/{}{}) |",
+            group_first_slot,
+            first_slot + ((group.end - 1) as u64),
+            slot_clock.estimate(group_first_slot),
+            group_first_slot,
+            cluster
+        );
+    }
+}
+
+// The query string that points explorer.solana.com at the cluster of url
+fn explorer_cluster(url : &str) -> String
+{
+    match url {
+        DEFAULT_MAINNET_RPC_URL => String::new(),
+        DEFAULT_TESTNET_RPC_URL => "?cluster=testnet".to_string(),
+        DEFAULT_DEVNET_RPC_URL => "?cluster=devnet".to_string(),
+        _ => format!(
+            "?cluster=custom&customUrl={}",
+            url.bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+                    _ => format!("%{:02X}", b)
+                })
+                .collect::<String>()
+        )
+    }
+}
+
+// One line per validator in the schedule's stakes, including those that won't lead any slots.  Rank is by stake
+// regardless of the order of the lines.
+fn report_summary(
+    epoch : u64,
+    sort : SummarySort,
+    leader_schedule : &LeaderSchedule,
+    stakes : &HashMap<Pubkey, u128>,
+    unit : StakeUnit
+)
+{
+    let slots = slot_counts(leader_schedule.get_slot_leaders());
+
+    let mut rows = stakes
+        .iter()
+        .map(|(pubkey, stake)| (*pubkey, *stake, slots.get(pubkey).copied().unwrap_or(0)))
+        .collect::<Vec<_>>();
+
+    rows.sort_by(|(l_pubkey, l_stake, _), (r_pubkey, r_stake, _)| r_stake.cmp(l_stake).then(l_pubkey.cmp(r_pubkey)));
+
+    let mut rows = rows.into_iter().enumerate().map(|(index, row)| (index + 1, row)).collect::<Vec<_>>();
+
+    match sort {
+        SummarySort::Stake => (),
+        SummarySort::Slots => rows.sort_by(|(l_rank, (_, _, l_slots)), (r_rank, (_, _, r_slots))| {
+            r_slots.cmp(l_slots).then(l_rank.cmp(r_rank))
+        }),
+        SummarySort::Pubkey => rows.sort_by_key(|(_, (pubkey, _, _))| *pubkey)
+    }
+
+    let total_slots = leader_schedule.num_slots() as f64;
+
+    println!("Leaders in {} (rank by stake, identity, slots, share of slots, effective stake):", epoch);
+
+    for (rank, (pubkey, stake, leader_slots)) in rows {
+        println!(
+            "{}  {}  {} slots  {:.3}%  {}",
+            rank,
+            pubkey,
+            leader_slots,
+            ((leader_slots as f64) * 100.0) / total_slots,
+            unit.format(stake)
+        );
+    }
+}
+
+// Estimates each leader's share of the stake from its share of the slots of an actual schedule, for checking stake
+// aggregation without the stake accounts.  Leaders are drawn in proportion to stake, one draw per group of
+// NUM_CONSECUTIVE_LEADER_SLOTS slots, so a share estimated from n draws has a standard error of sqrt(p * (1 - p) / n).
+fn report_inferred_stakes(
+    epoch : u64,
+    leaders : &[Pubkey]
+)
+{
+    let mut rows = slot_counts(leaders).into_iter().collect::<Vec<_>>();
+
+    rows.sort_by(|(l_pubkey, l_slots), (r_pubkey, r_slots)| r_slots.cmp(l_slots).then(l_pubkey.cmp(r_pubkey)));
+
+    let draws = ((leaders.len() as u64) / NUM_CONSECUTIVE_LEADER_SLOTS).max(1) as f64;
+
+    println!(
+        "Stake inferred from the schedule of {} (identity, slots, estimated share of stake, standard error):",
+        epoch
+    );
+
+    for (pubkey, slots) in rows {
+        let share = (slots as f64) / (leaders.len() as f64);
+        println!(
+            "{}  {} slots  {:.3}%  +/- {:.3}%",
+            pubkey,
+            slots,
+            share * 100.0,
+            ((share * (1.0 - share)) / draws).sqrt() * 100.0
+        );
+    }
+}
+
+// Per validator, the change in slot count and stake share from the current epoch, as given by the cluster's schedule
+// and the stakes reconstructed for it, to the next: the validators gaining the most slots first and losing the most
+// last.  Each of current and next is the epoch, its leaders, and its stake per identity.
+fn report_diff_current(
+    output_format : OutputFormat,
+    current : (u64, &[Pubkey], &HashMap<Pubkey, u128>),
+    next : (u64, &[Pubkey], &HashMap<Pubkey, u128>)
+)
+{
+    let (current_epoch, current_leaders, current_stakes) = current;
+    let (next_epoch, next_leaders, next_stakes) = next;
+
+    let current_slots = slot_counts(current_leaders);
+    let next_slots = slot_counts(next_leaders);
+
+    let share = |stakes : &HashMap<Pubkey, u128>, identity : &Pubkey| {
+        let total = stakes.values().sum::<u128>();
+        if total == 0 {
+            0.0
+        }
+        else {
+            ((stakes.get(identity).copied().unwrap_or(0) as f64) * 100.0) / (total as f64)
+        }
+    };
+
+    let mut identities = current_slots.keys().chain(next_slots.keys()).copied().collect::<Vec<_>>();
+    identities.sort_unstable();
+    identities.dedup();
+
+    let mut changes = identities
+        .into_iter()
+        .map(|identity| {
+            let current = current_slots.get(&identity).copied().unwrap_or(0);
+            let next = next_slots.get(&identity).copied().unwrap_or(0);
+            output::LeaderChange {
+                identity : identity.to_string(),
+                current_slots : current,
+                next_slots : next,
+                current_stake_share : share(current_stakes, &identity),
+                next_stake_share : share(next_stakes, &identity),
+                change : match (current, next) {
+                    (0, _) => "new",
+                    (_, 0) => "dropped",
+                    (current, next) if next > current => "gaining",
+                    (current, next) if next < current => "losing",
+                    _ => "unchanged"
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    changes.sort_by(|l, r| {
+        let delta = |change : &output::LeaderChange| (change.next_slots as i64) - (change.current_slots as i64);
+        delta(r).cmp(&delta(l)).then(l.identity.cmp(&r.identity))
+    });
+
+    if output_format == OutputFormat::Json {
+        output::write_leader_changes_json(&mut std::io::stdout().lock(), current_epoch, next_epoch, &changes)
+            .unwrap_or_else(|e| error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write changes: {}", e)));
+        return;
+    }
+
+    println!(
+        "Changes from {} to {} (identity, slots, change in slots, share of stake, change in share of stake):",
+        current_epoch, next_epoch
+    );
+
+    for change in changes {
+        println!(
+            "{}  {} -> {} slots  {:+}  {:.3}% -> {:.3}%  {:+.3}  {}",
+            change.identity,
+            change.current_slots,
+            change.next_slots,
+            (change.next_slots as i64) - (change.current_slots as i64),
+            change.current_stake_share,
+            change.next_stake_share,
+            change.next_stake_share - change.current_stake_share,
+            change.change
+        );
+    }
+}
+
+// Lists the validators whose stake equals another's.  The schedule orders such validators by pubkey, in descending
+// order, so their slots depend on that tie-break as well as on stake, which makes them where independent
+// implementations are most likely to differ.
+fn report_ties(
+    epoch : u64,
+    leader_schedule : &LeaderSchedule,
+    stakes : &HashMap<Pubkey, u128>,
+    unit : StakeUnit
+)
+{
+    let stakes = schedule_stakes(stakes).unwrap_or_else(|e| predictor_error(e));
+
+    let slots = slot_counts(leader_schedule.get_slot_leaders());
+
+    let mut any = false;
+
+    let mut start = 0;
+    while start < stakes.len() {
+        let end = start + stakes[start..].iter().take_while(|(_, stake)| *stake == stakes[start].1).count();
+        if (end - start) > 1 {
+            if !any {
+                println!(
+                    "Validators with equal stake in {} (in the order the schedule takes them, with their slots):",
+                    epoch
+                );
+                any = true;
+            }
+            println!("{}:", unit.format(stakes[start].1 as u128));
+            for (pubkey, _) in &stakes[start..end] {
+                println!("  {}  {} slots", pubkey, slots.get(pubkey).copied().unwrap_or(0));
+            }
+        }
+        start = end;
+    }
+
+    if !any {
+        println!("No two validators have equal stake in {}", epoch);
+    }
+}
+
+// Shows the share of the epoch's slots that will be led by validators running each software version, as currently
+// advertised in gossip; leaders not in gossip are counted as "unknown"
+fn report_versions(
+    rpc_client : &RpcClient,
+    epoch : u64,
+    leader_schedule : &LeaderSchedule
+)
+{
+    let node_versions = rpc_client
+        .get_cluster_nodes()
+        .unwrap_or_else(|e| error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch cluster nodes: {}", e)))
+        .into_iter()
+        .filter_map(|node| Some((node.pubkey.parse::<Pubkey>().ok()?, node.version?)))
+        .collect::<HashMap<Pubkey, String>>();
+
+    let mut version_slots = BTreeMap::<&str, u64>::new();
+
+    for (leader, slots) in slot_counts(leader_schedule.get_slot_leaders()) {
+        *(version_slots.entry(node_versions.get(&leader).map_or("unknown", String::as_str)).or_insert(0)) += slots;
+    }
+
+    let mut version_slots = version_slots.into_iter().collect::<Vec<_>>();
+
+    version_slots.sort_by(|(l_version, l_slots), (r_version, r_slots)| {
+        r_slots.cmp(l_slots).then(l_version.cmp(r_version))
+    });
+
+    let total_slots = leader_schedule.num_slots() as f64;
+
+    println!("Slots in {} by leader software version:", epoch);
+
+    for (version, slots) in version_slots {
+        println!("{}  {} slots  {:.3}%", version, slots, ((slots as f64) * 100.0) / total_slots);
+    }
+}
+
+// Lists the counted stake accounts that are under lockup, and each vote account's stake split into locked and
+// unlocked.  Locked stake cannot be withdrawn (though it can still be deactivated or redelegated) before the lockup
+// expires.
+fn report_lockups(
+    epoch : u64,
+    stakes : &HashMap<Pubkey, u128>,
+    locked : &[(Pubkey, StakeDelegation)],
+    unit : StakeUnit
+)
+{
+    let mut locked_stakes = HashMap::<Pubkey, u128>::default();
+
+    for (_, delegation) in locked {
+        *(locked_stakes.entry(delegation.voter_pubkey).or_insert(0)) += delegation.stake as u128;
+    }
+
+    let mut by_voter = stakes
+        .iter()
+        .map(|(voter, stake)| {
+            let locked_stake = locked_stakes.get(voter).copied().unwrap_or(0);
+            (*voter, locked_stake, stake.saturating_sub(locked_stake))
+        })
+        .collect::<Vec<_>>();
+
+    by_voter.sort_by(|(l_voter, l_locked, _), (r_voter, r_locked, _)| {
+        r_locked.cmp(l_locked).then(l_voter.cmp(r_voter))
+    });
+
+    println!("Locked and unlocked stake in {} by vote account:", epoch);
+
+    for (voter, locked_stake, unlocked_stake) in by_voter {
+        println!("{}  {} locked  {} unlocked", voter, unit.format(locked_stake), unit.format(unlocked_stake));
+    }
+
+    let mut locked = locked.iter().collect::<Vec<_>>();
+
+    locked.sort_by(|(l_pubkey, l), (r_pubkey, r)| r.stake.cmp(&l.stake).then(l_pubkey.cmp(r_pubkey)));
+
+    println!();
+    println!("Stake accounts under lockup:");
+
+    for (pubkey, delegation) in locked {
+        let until = DateTime::<Utc>::from_timestamp(delegation.lockup.unix_timestamp, 0).map_or_else(
+            || delegation.lockup.unix_timestamp.to_string(),
+            |until| until.format("%Y-%m-%d %H:%M UTC").to_string()
+        );
+        println!(
+            "{}  {} to {}  until epoch {} / {}  custodian {}",
+            pubkey,
+            unit.format(delegation.stake as u128),
+            delegation.voter_pubkey,
+            delegation.lockup.epoch,
+            until,
+            delegation.lockup.custodian
+        );
+    }
+}
+
+// Vote accounts which exist but have no counted stake will get no leader slots; this is most often the result of a
+// delegation mistake (stake delegated to the wrong vote account, or not yet activated) and so is worth calling out
+fn report_zero_stake(
+    rpc_client : &RpcClient,
+    epoch : u64,
+    stakes : &HashMap<Pubkey, u128>
+)
+{
+    let vote_accounts = rpc_client
+        .get_vote_accounts()
+        .unwrap_or_else(|e| error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch vote accounts: {}", e)));
+
+    let mut zero_stake = vote_accounts
+        .current
+        .iter()
+        .chain(vote_accounts.delinquent.iter())
+        .filter(|vote_account| {
+            vote_account
+                .vote_pubkey
+                .parse::<Pubkey>()
+                .ok()
+                .is_none_or(|pubkey| stakes.get(&pubkey).copied().unwrap_or(0) == 0)
+        })
+        .collect::<Vec<_>>();
+
+    zero_stake.sort_by(|l, r| l.vote_pubkey.cmp(&r.vote_pubkey));
+
+    println!("The following vote accounts will have zero stake, and thus no leader slots, in {}:", epoch);
+
+    for vote_account in zero_stake {
+        println!("{} (identity {})", vote_account.vote_pubkey, vote_account.node_pubkey);
+    }
+}
+
+// Maps each validator identity to its location, as looked up from the validator's gossip IP address in an
+// offline MaxMind GeoIP2/GeoLite2 City database
+fn leader_locations(
+    rpc_client : &RpcClient,
+    geoip : &str
+) -> HashMap<Pubkey, String>
+{
+    let reader = maxminddb::Reader::open_readfile(geoip)
+        .unwrap_or_else(|e| {
+            error_exit(ErrorCode::GeoipError, format!("ERROR: Failed to open GeoIP database {}: {}", geoip, e))
+        });
+
+    rpc_client
+        .get_cluster_nodes()
+        .unwrap_or_else(|e| error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch cluster nodes: {}", e)))
+        .into_iter()
+        .filter_map(|node| Some((node.pubkey.parse::<Pubkey>().ok()?, describe_location(&reader, node.gossip?.ip()))))
+        .collect()
+}
+
+// Checks the identity of each vote account in the cached stakes of the next epoch, and the cached validator info, of
+// the cluster at predictor against the chain, and corrects those that are stale in the cache.  Each can be checked in
+// full with one request, so nothing is left to a sample.  Damaged chunks of cached stakes are repaired first.
+fn report_cache_verification(
+    predictor : &LeaderSchedulePredictor,
+    epoch_info : &EpochInfo
+)
+{
+    let rpc_client = predictor.rpc_client();
+
+    let dir = cache_dir(rpc_client).unwrap_or_else(|| {
+        error_exit(ErrorCode::ReadError, format!("ERROR: There is no cache directory for {}", rpc_client.url()))
+    });
+
+    let epoch = epoch_info.epoch + 1;
+
+    let stakes = read_stakes_cache(&dir, epoch)
+        .and_then(|(stakes, damaged)| repair_stakes_cache(predictor, epoch_info, &dir, stakes, damaged));
+
+    match stakes {
+        Some(stakes) => {
+            let vote_accounts = rpc_client.get_vote_accounts().unwrap_or_else(|e| {
+                error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch vote accounts: {}", e))
+            });
+            let chain_identities = vote_accounts
+                .current
+                .iter()
+                .chain(vote_accounts.delinquent.iter())
+                .filter_map(|vote_account| {
+                    let vote_pubkey = vote_account.vote_pubkey.parse::<Pubkey>().ok()?;
+                    Some((vote_pubkey, vote_account.node_pubkey.parse::<Pubkey>().ok()?))
+                })
+                .collect::<HashMap<Pubkey, Pubkey>>();
+
+            let Stakes { first_slot, slots_in_epoch, vote_stakes, mut identities, .. } = stakes;
+            let checked = identities.len();
+            let mut stale = 0;
+            for (vote_pubkey, identity) in identities.iter_mut() {
+                let chain_identity = chain_identities.get(vote_pubkey).copied();
+                if let Some(chain_identity) = chain_identity.filter(|chain_identity| *chain_identity != *identity) {
+                    println!("Vote account {} now votes for {}, not {}", vote_pubkey, chain_identity, identity);
+                    *identity = chain_identity;
+                    stale += 1;
+                }
+            }
+            println!("Cached stakes of {}: {} vote account identities checked, {} stale", epoch, checked, stale);
+            if stale > 0 {
+                write_stakes_cache(&dir, &Stakes::new(epoch, first_slot, slots_in_epoch, vote_stakes, identities));
+            }
+        },
+        None => println!("Cached stakes of {}: none", epoch)
+    }
+
+    let path = dir.join("validator-info");
+
+    match read_validator_names_cache(&path) {
+        Some(cached) => {
+            let names = validator_names(rpc_client, None, true);
+            let stale =
+                cached.iter().filter(|(identity, description)| names.get(*identity) != Some(*description)).count();
+            let missing = names.keys().filter(|identity| !cached.contains_key(*identity)).count();
+            println!("Cached validator info: {} checked, {} stale, {} missing", cached.len(), stale, missing);
+            if (stale > 0) || (missing > 0) {
+                write_validator_names_cache(&path, &names);
+            }
+        },
+        None => println!("Cached validator info: none")
+    }
+}
+
+// Maps each validator identity to the name, website, and keybase username published in its validator info account in
+// the config program, tab-separated and with - for any not given.  The mapping is cached in dir, if any, and reused
+// until it is VALIDATOR_INFO_CACHE_TTL old unless refresh.
+fn validator_names(
+    rpc_client : &RpcClient,
+    dir : Option<&Path>,
+    refresh : bool
+) -> HashMap<Pubkey, String>
+{
+    let path = dir.map(|dir| dir.join("validator-info"));
+
+    if !refresh {
+        if let Some(names) = path.as_deref().and_then(read_validator_names_cache) {
+            return names;
+        }
+    }
+
+    // Validator info accounts list the validator info key first, after the one byte length of the key list
+    let names = rpc_client
+        .get_program_accounts_with_config(&solana_sdk::config::program::id(), RpcProgramAccountsConfig {
+            filters : Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                1,
+                validator_info::id().to_bytes().to_vec()
+            ))]),
+            ..RpcProgramAccountsConfig::default()
+        })
+        .unwrap_or_else(|e| error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch validator info: {}", e)))
+        .into_iter()
+        .filter_map(|(pubkey, account)| describe_validator_info(&pubkey, &account.data))
+        .collect::<HashMap<Pubkey, String>>();
+
+    if let Some(path) = &path {
+        write_validator_names_cache(path, &names);
+    }
+
+    names
+}
+
+// The identity that signed a validator info account, and its name, website, and keybase username as described for
+// validator_names.  None for accounts that aren't validator info.
+fn describe_validator_info(
+    pubkey : &Pubkey,
+    data : &[u8]
+) -> Option<(Pubkey, String)>
+{
+    let config = match parse_config(data, pubkey).ok()? {
+        ConfigAccountType::ValidatorInfo(config) => config,
+        _ => return None
+    };
+
+    let identity = config.keys.iter().find(|key| key.signer)?.pubkey.parse::<Pubkey>().ok()?;
+
+    // Published values are free text, so anything that would break the tab-separated line is blanked
+    let field = |name : &str| {
+        config
+            .config_data
+            .get(name)
+            .and_then(serde_json::Value::as_str)
+            .map(|value| value.chars().map(|c| if c.is_control() { ' ' } else { c }).collect::<String>())
+            .filter(|value| !value.trim().is_empty())
+            .map_or_else(|| "-".to_string(), |value| value.trim().to_string())
+    };
+
+    Some((identity, format!("{}\t{}\t{}", field("name"), field("website"), field("keybaseUsername"))))
+}
+
+// The cached validator info file holds one line per identity of its pubkey, a tab, and its description.  A file that
+// is too old or can't be read is treated as absent.
+fn read_validator_names_cache(path : &Path) -> Option<HashMap<Pubkey, String>>
+{
+    if std::fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()? > VALIDATOR_INFO_CACHE_TTL {
+        return None;
+    }
+
+    std::fs::read_to_string(path)
+        .ok()?
+        .lines()
+        .map(|line| {
+            let (identity, description) = line.split_once('\t')?;
+            Some((identity.parse::<Pubkey>().ok()?, description.to_string()))
+        })
+        .collect()
+}
+
+// As with cached stakes, written under another name and renamed into place, and only warned about on failure
+fn write_validator_names_cache(
+    path : &Path,
+    names : &HashMap<Pubkey, String>
+)
+{
+    let mut sorted = names.iter().collect::<Vec<_>>();
+
+    sorted.sort_by_key(|(identity, _)| *identity);
+
+    let contents = sorted
+        .into_iter()
+        .map(|(identity, description)| format!("{}\t{}\n", identity, description))
+        .collect::<String>();
+
+    let partial = path.with_extension(std::process::id().to_string());
+
+    if let Err(e) = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&partial, contents))
+        .and_then(|_| std::fs::rename(&partial, path))
+    {
+        eprintln!("WARNING: Failed to cache validator info in {}: {}", path.display(), e);
+    }
+}
+
+// Writes the per-slot leader identity and TPU address in the leader hints format described at LEADER_HINTS_MAGIC, for
+// consumption by transaction senders which forward directly to upcoming leaders
+fn write_leader_hints(
+    rpc_client : &RpcClient,
+    path : &str,
+    first_slot : u64,
+    leader_schedule : &LeaderSchedule
+)
+{
+    let tpus = rpc_client
+        .get_cluster_nodes()
+        .unwrap_or_else(|e| error_exit(ErrorCode::RpcError, format!("ERROR: Failed to fetch cluster nodes: {}", e)))
+        .into_iter()
+        .filter_map(|node| Some((node.pubkey.parse::<Pubkey>().ok()?, node.tpu?)))
+        .collect::<HashMap<Pubkey, SocketAddr>>();
+
+    let leaders = leader_schedule.get_slot_leaders();
+
+    let mut data = Vec::with_capacity(10 + (leaders.len() * 58));
+
+    data.extend_from_slice(LEADER_HINTS_MAGIC);
+    data.extend_from_slice(&LEADER_HINTS_VERSION.to_le_bytes());
+    data.extend_from_slice(&(leaders.len() as u32).to_le_bytes());
+
+    for (index, identity) in leaders.iter().enumerate() {
+        let tpu = tpus.get(identity);
+        let ip = match tpu.map(SocketAddr::ip) {
+            Some(IpAddr::V4(ip)) => ip.to_ipv6_mapped().octets(),
+            Some(IpAddr::V6(ip)) => ip.octets(),
+            None => [0_u8; 16]
+        };
+        data.extend_from_slice(&(first_slot + (index as u64)).to_le_bytes());
+        data.extend_from_slice(identity.as_ref());
+        data.extend_from_slice(&ip);
+        data.extend_from_slice(&tpu.map_or(0, SocketAddr::port).to_le_bytes());
+    }
+
+    std::fs::write(path, data).unwrap_or_else(|e| {
+        error_exit(ErrorCode::WriteError, format!("ERROR: Failed to write leader hints to {}: {}", path, e))
+    });
+}
+
+fn describe_location(
+    reader : &maxminddb::Reader<Vec<u8>>,
+    ip : IpAddr
+) -> String
+{
+    let city = reader.lookup::<maxminddb::geoip2::City>(ip).ok();
+
+    let country = city.as_ref().and_then(|city| city.country.as_ref()).and_then(|country| country.iso_code);
+
+    let location = city.as_ref().and_then(|city| city.location.as_ref());
+
+    let or_dash = |value : Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+    format!(
+        "{}\t{}\t{}\t{}",
+        ip,
+        or_dash(country.map(str::to_string)),
+        or_dash(location.and_then(|location| location.latitude).map(|latitude| latitude.to_string())),
+        or_dash(location.and_then(|location| location.longitude).map(|longitude| longitude.to_string()))
+    )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // A directory of its own under the system temporary directory, for tests that write files
+    fn test_dir(name : &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("solana_leader_schedule-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    #[test]
+    fn stake_unit_rounds_sol_exactly()
+    {
+        assert_eq!(StakeUnit::Lamports.format(1_234_567_890), "1234567890 lamports");
+        assert_eq!(StakeUnit::Sol(9).format(1_234_567_890), "1.234567890 SOL");
+        assert_eq!(StakeUnit::Sol(2).format(1_234_567_890), "1.23 SOL");
+        assert_eq!(StakeUnit::Sol(2).format(1_235_000_000), "1.24 SOL");
+        assert_eq!(StakeUnit::Sol(0).format(1_500_000_000), "2 SOL");
+        assert_eq!(StakeUnit::Sol(3).format(5_000_000), "0.005 SOL");
+        assert_eq!(StakeUnit::Sol(3).format(0), "0.000 SOL");
+
+        // Far more stake than exists still rounds exactly
+        assert_eq!(StakeUnit::Sol(1).format((u64::MAX as u128) * 1_000), "18446744073709.6 SOL");
+    }
+
+    #[test]
+    fn timeline_row_marks_cells_led()
+    {
+        let identity = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        // Slots that don't divide evenly into cells leave a shorter last cell rather than more cells than the width
+        let mut leaders = vec![other; (TIMELINE_WIDTH * 4) + 1];
+        leaders[0] = identity;
+        leaders[TIMELINE_WIDTH * 4] = identity;
+        let row = timeline_row(&leaders, &identity).chars().collect::<Vec<char>>();
+        assert!(row.len() <= TIMELINE_WIDTH);
+        assert_eq!(row.first(), Some(&'\u{2588}'));
+        assert_eq!(row.last(), Some(&'\u{2588}'));
+        assert_eq!(row.iter().filter(|cell| **cell == '\u{2588}').count(), 2);
+
+        // Epochs shorter than the width get one slot per cell
+        let row = timeline_row(&[other, identity, other], &identity);
+        assert_eq!(row, "\u{00b7}\u{2588}\u{00b7}");
+
+        assert_eq!(timeline_row(&[], &identity), "");
+    }
+
+    #[test]
+    fn stakes_cache_round_trips()
+    {
+        let dir = test_dir("stakes-cache-round-trip");
+        let vote_pubkey = Pubkey::new_unique();
+        let identity = Pubkey::new_unique();
+
+        write_stakes_cache(
+            &dir,
+            &Stakes::new(
+                11,
+                4_752_000,
+                432_000,
+                HashMap::from_iter([(vote_pubkey, 1_000), (Pubkey::new_unique(), 2_000)]),
+                HashMap::from_iter([(vote_pubkey, identity)])
+            )
+        );
+
+        let (stakes, damaged) = read_stakes_cache(&dir, 11).unwrap();
+        assert!(damaged.is_empty());
+        assert_eq!((stakes.first_slot, stakes.slots_in_epoch), (4_752_000, 432_000));
+        assert_eq!(stakes.vote_stakes.len(), 2);
+        assert_eq!(stakes.vote_stakes.get(&vote_pubkey), Some(&1_000));
+        assert_eq!(stakes.identities.get(&vote_pubkey), Some(&identity));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stakes_cache_ignores_other_versions_and_damaged_files()
+    {
+        let dir = test_dir("stakes-cache-damaged");
+        let vote_pubkey = Pubkey::new_unique();
+        let line = format!("{} - 1000", vote_pubkey);
+        let chunk = format!("chunk {} {} {}\n{}\n", vote_pubkey, vote_pubkey, stakes_cache_checksum(&[&line]), line);
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for contents in [
+            format!("432000 4752000\n{}", chunk),
+            format!("stakes-cache-v1 432000 4752000\n{}", chunk),
+            format!("{} 432000\n{}", STAKES_CACHE_VERSION, chunk),
+            format!("{} 432000 4752000\n", STAKES_CACHE_VERSION),
+            format!("{} 432000 4752000\n{}\n", STAKES_CACHE_VERSION, line),
+            format!("{} 432000 4752000\n{}\n{}", STAKES_CACHE_VERSION, line, chunk),
+            format!("{} 432000 4752000\nchunk {} {}\n{}\n", STAKES_CACHE_VERSION, vote_pubkey, vote_pubkey, line),
+            format!("{} 432000 4752000\n{}", STAKES_CACHE_VERSION, chunk.replace("1000", "1001")),
+            String::new()
+        ] {
+            std::fs::write(stakes_cache_path(&dir, 11), &contents).unwrap();
+            assert!(read_stakes_cache(&dir, 11).is_none(), "{:?}", contents);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stakes_cache_finds_damaged_chunks()
+    {
+        let dir = test_dir("stakes-cache-damaged-chunks");
+        let mut vote_pubkeys =
+            (0..((STAKES_CACHE_CHUNK_SIZE * 2) + 1)).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        vote_pubkeys.sort_unstable();
+
+        write_stakes_cache(
+            &dir,
+            &Stakes::new(
+                11,
+                4_752_000,
+                432_000,
+                vote_pubkeys.iter().map(|vote_pubkey| (*vote_pubkey, 1_000)).collect(),
+                HashMap::default()
+            )
+        );
+
+        let path = stakes_cache_path(&dir, 11);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let second_chunk = (vote_pubkeys[STAKES_CACHE_CHUNK_SIZE], vote_pubkeys[(STAKES_CACHE_CHUNK_SIZE * 2) - 1]);
+
+        // A changed stake and a lost line each damage only the chunk that they are in
+        for damage in [
+            contents.replace(&format!("{} - 1000", second_chunk.1), &format!("{} - 1001", second_chunk.1)),
+            contents.replace(&format!("{} - 1000\n", second_chunk.1), "")
+        ] {
+            std::fs::write(&path, damage).unwrap();
+            let (stakes, damaged) = read_stakes_cache(&dir, 11).unwrap();
+            assert_eq!(damaged, vec![second_chunk]);
+            assert_eq!(stakes.vote_stakes.len(), STAKES_CACHE_CHUNK_SIZE + 1);
+            assert!(!stakes.vote_stakes.contains_key(&second_chunk.0));
+            assert!(stakes.vote_stakes.contains_key(&vote_pubkeys[0]));
+            assert!(stakes.vote_stakes.contains_key(&vote_pubkeys[STAKES_CACHE_CHUNK_SIZE * 2]));
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stakes_cache_prunes_earlier_epochs()
+    {
+        let dir = test_dir("stakes-cache-prune");
+        let stakes = |epoch| {
+            Stakes::new(epoch, 0, 32, HashMap::from_iter([(Pubkey::new_unique(), 1)]), HashMap::default())
+        };
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("validator-info"), "").unwrap();
+
+        write_stakes_cache(&dir, &stakes(9));
+        write_stakes_cache(&dir, &stakes(10));
+
+        assert!(!stakes_cache_path(&dir, 9).exists());
+        assert!(stakes_cache_path(&dir, 10).exists());
+        assert!(dir.join("validator-info").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn display_zone_parses_names_and_offsets()
+    {
+        assert!(matches!("UTC".parse::<DisplayZone>(), Ok(DisplayZone::Utc)));
+        assert!(matches!("local".parse::<DisplayZone>(), Ok(DisplayZone::Local)));
+        assert!(matches!(
+            "+05:30".parse::<DisplayZone>(),
+            Ok(DisplayZone::Offset(offset)) if offset.local_minus_utc() == ((5 * 3600) + (30 * 60))
+        ));
+        assert!(matches!(
+            "-08:00".parse::<DisplayZone>(),
+            Ok(DisplayZone::Offset(offset)) if offset.local_minus_utc() == -(8 * 3600)
+        ));
+
+        assert!("05:30".parse::<DisplayZone>().is_err());
+        assert!("+05".parse::<DisplayZone>().is_err());
+        assert!("+05:60".parse::<DisplayZone>().is_err());
+        assert!("+24:00".parse::<DisplayZone>().is_err());
+        assert!("pst".parse::<DisplayZone>().is_err());
+    }
+
+    #[test]
+    fn validate_url_catches_common_mistakes()
+    {
+        assert_eq!(validate_url("https://api.mainnet-beta.solana.com"), Ok(()));
+        assert_eq!(validate_url("http://localhost:8899/"), Ok(()));
+        assert_eq!(validate_url("http://[::1]:8899"), Ok(()));
+        assert_eq!(validate_url("http://[::1]"), Ok(()));
+
+        assert!(validate_url("api.mainnet-beta.solana.com").is_err());
+        assert!(validate_url("mainnet").is_err());
+        assert!(validate_url("http://").is_err());
+        assert!(validate_url("http://:8899").is_err());
+        assert!(validate_url("http://localhost:88990").is_err());
+        assert!(validate_url("http://localhost:port").is_err());
+
+        if cfg!(unix) {
+            assert_eq!(validate_url("unix:///var/run/solana-rpc.sock"), Ok(()));
+            assert!(validate_url("unix://").is_err());
+        }
+    }
 }
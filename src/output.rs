@@ -0,0 +1,457 @@
+// Writes the per-slot leader schedule in the format selected with --output-format
+use serde::Serialize;
+use solana_sdk::clock::NUM_CONSECUTIVE_LEADER_SLOTS;
+use solana_sdk::hash::hashv;
+use solana_sdk::pubkey::Pubkey;
+use std::io::Write;
+use std::ops::Range;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat
+{
+    Text,
+    Json,
+    Csv,
+    // Only for --impact, which compares two schedules
+    Diff
+}
+
+impl std::str::FromStr for OutputFormat
+{
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err>
+    {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "diff" => Ok(OutputFormat::Diff),
+            _ => Err(format!("ERROR: Invalid output format {}: expected one of text, json, csv, diff", s))
+        }
+    }
+}
+
+impl OutputFormat
+{
+    // The extension of files written in the format
+    pub fn extension(&self) -> &'static str
+    {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Diff => "diff"
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSchedule<'a>
+{
+    epoch : u64,
+
+    slots : Vec<JsonSlot>,
+
+    timings : Vec<JsonTiming<'a>>
+}
+
+// The time taken by one phase of the run that computed the schedule, so that consumers can monitor the pipeline
+#[derive(Serialize)]
+struct JsonTiming<'a>
+{
+    phase : &'a str,
+
+    seconds : f64
+}
+
+#[derive(Serialize)]
+struct JsonSlot
+{
+    slot : u64,
+
+    slot_index : u64,
+
+    leader : String
+}
+
+#[derive(Serialize)]
+struct JsonWeights
+{
+    epoch : u64,
+
+    seed : String,
+
+    slots_in_epoch : u64,
+
+    consecutive_leader_slots : u64,
+
+    weights : Vec<JsonWeight>
+}
+
+#[derive(Serialize)]
+struct JsonWeight
+{
+    pubkey : String,
+
+    stake : u64
+}
+
+#[derive(Serialize)]
+struct JsonWindow<'a>
+{
+    epoch : u64,
+
+    start_slot : u64,
+
+    end_slot : u64,
+
+    slots : &'a [JsonSlot],
+
+    sha256 : String
+}
+
+// The ranges of slot indexes to write: the first head and last tail slots, or all slots when neither is given
+pub fn shown_ranges(
+    len : usize,
+    head : Option<usize>,
+    tail : Option<usize>
+) -> Vec<Range<usize>>
+{
+    if head.is_none() && tail.is_none() {
+        return std::iter::once(0..len).collect();
+    }
+
+    let head_end = head.unwrap_or(0).min(len);
+    let tail_start = len.saturating_sub(tail.unwrap_or(0)).max(head_end);
+
+    vec![0..head_end, tail_start..len].into_iter().filter(|range| !range.is_empty()).collect()
+}
+
+// One line per slot as produced by line, with a "..." line wherever slots are left out between ranges
+pub fn write_text(
+    out : &mut impl Write,
+    leaders : &[Pubkey],
+    ranges : &[Range<usize>],
+    mut line : impl FnMut(&Pubkey) -> String
+) -> std::io::Result<()>
+{
+    for (i, range) in ranges.iter().enumerate() {
+        if i > 0 {
+            writeln!(out, "...")?;
+        }
+        for leader in &leaders[range.clone()] {
+            writeln!(out, "{}", line(leader))?;
+        }
+    }
+    Ok(())
+}
+
+// One line per group of consecutive slots with the same leader, of the group's first and last slot and its leader, with
+// a "..." line wherever slots are left out between ranges
+pub fn write_text_groups(
+    out : &mut impl Write,
+    first_slot : u64,
+    leaders : &[Pubkey],
+    ranges : &[Range<usize>]
+) -> std::io::Result<()>
+{
+    for (i, range) in ranges.iter().enumerate() {
+        if i > 0 {
+            writeln!(out, "...")?;
+        }
+        for run in leader_runs(leaders, range.clone()) {
+            writeln!(
+                out,
+                "{}-{}  {}",
+                first_slot + (run.start as u64),
+                first_slot + ((run.end - 1) as u64),
+                leaders[run.start]
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_json(
+    out : &mut impl Write,
+    epoch : u64,
+    first_slot : u64,
+    leaders : &[Pubkey],
+    ranges : &[Range<usize>],
+    timings : &[(&str, Duration)]
+) -> std::io::Result<()>
+{
+    let slots = ranges
+        .iter()
+        .flat_map(|range| range.clone())
+        .map(|index| JsonSlot {
+            slot : first_slot + (index as u64),
+            slot_index : index as u64,
+            leader : leaders[index].to_string()
+        })
+        .collect();
+
+    let timings =
+        timings.iter().map(|(phase, elapsed)| JsonTiming { phase, seconds : elapsed.as_secs_f64() }).collect();
+
+    serde_json::to_writer(&mut *out, &JsonSchedule { epoch, slots, timings })?;
+    writeln!(out)
+}
+
+// A standalone record of the leaders of one window of slots.  sha256 is the SHA-256 of the slots array exactly as it
+// is serialized in the file, so that the window can be checked against a copy attached elsewhere.
+pub fn write_window(
+    out : &mut impl Write,
+    epoch : u64,
+    first_slot : u64,
+    leaders : &[Pubkey],
+    window : Range<usize>
+) -> std::io::Result<()>
+{
+    let slots = window
+        .map(|index| JsonSlot {
+            slot : first_slot + (index as u64),
+            slot_index : index as u64,
+            leader : leaders[index].to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let sha256 = hashv(&[&serde_json::to_vec(&slots)?]).to_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+
+    serde_json::to_writer(
+        &mut *out,
+        &JsonWindow {
+            epoch,
+            start_slot : slots.first().map_or(0, |slot| slot.slot),
+            end_slot : slots.last().map_or(0, |slot| slot.slot),
+            slots : &slots,
+            sha256
+        }
+    )?;
+    writeln!(out)
+}
+
+// Everything passed to LeaderSchedule::new, so that the schedule can be derived again by an independent
+// implementation: weights is the (pubkey, stake) vector in the order passed, and seed is the hex of the seed's bytes
+pub fn write_weights(
+    out : &mut impl Write,
+    epoch : u64,
+    slots_in_epoch : u64,
+    seed : &[u8; 32],
+    weights : &[(Pubkey, u64)]
+) -> std::io::Result<()>
+{
+    serde_json::to_writer(
+        &mut *out,
+        &JsonWeights {
+            epoch,
+            seed : seed.iter().map(|b| format!("{:02x}", b)).collect(),
+            slots_in_epoch,
+            consecutive_leader_slots : NUM_CONSECUTIVE_LEADER_SLOTS,
+            weights : weights
+                .iter()
+                .map(|(pubkey, stake)| JsonWeight { pubkey : pubkey.to_string(), stake : *stake })
+                .collect()
+        }
+    )?;
+    writeln!(out)
+}
+
+// How one validator's share of the schedule changes from the current epoch to the next.  change is one of new,
+// dropped, gaining, losing, or unchanged, by slot count.
+#[derive(Serialize)]
+pub struct LeaderChange
+{
+    pub identity : String,
+
+    pub current_slots : u64,
+
+    pub next_slots : u64,
+
+    pub current_stake_share : f64,
+
+    pub next_stake_share : f64,
+
+    pub change : &'static str
+}
+
+#[derive(Serialize)]
+struct JsonLeaderChanges<'a>
+{
+    current_epoch : u64,
+
+    next_epoch : u64,
+
+    validators : &'a [LeaderChange]
+}
+
+pub fn write_leader_changes_json(
+    out : &mut impl Write,
+    current_epoch : u64,
+    next_epoch : u64,
+    changes : &[LeaderChange]
+) -> std::io::Result<()>
+{
+    serde_json::to_writer(&mut *out, &JsonLeaderChanges { current_epoch, next_epoch, validators : changes })?;
+    writeln!(out)
+}
+
+// Written once before the rows of however many epochs follow
+pub fn write_csv_header(out : &mut impl Write) -> std::io::Result<()>
+{
+    writeln!(out, "epoch,first_slot,last_slot,first_slot_index,last_slot_index,leader")
+}
+
+// One row per group of consecutive slots with the same leader
+pub fn write_csv(
+    out : &mut impl Write,
+    epoch : u64,
+    first_slot : u64,
+    leaders : &[Pubkey],
+    ranges : &[Range<usize>]
+) -> std::io::Result<()>
+{
+    for range in ranges {
+        for run in leader_runs(leaders, range.clone()) {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                epoch,
+                first_slot + (run.start as u64),
+                first_slot + ((run.end - 1) as u64),
+                run.start,
+                run.end - 1,
+                leaders[run.start]
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The index ranges of the runs of consecutive slots within range that have the same leader
+fn leader_runs(
+    leaders : &[Pubkey],
+    range : Range<usize>
+) -> impl Iterator<Item = Range<usize>> + '_
+{
+    let mut start = range.start;
+    std::iter::from_fn(move || {
+        if start >= range.end {
+            return None;
+        }
+        let mut end = start + 1;
+        while (end < range.end) && (leaders[end] == leaders[start]) {
+            end += 1;
+        }
+        let run = start..end;
+        start = end;
+        Some(run)
+    })
+}
+
+// The slots whose leader differs between two schedules of the same epoch, in the style of a unified diff: one hunk per
+// group of consecutive slots with the same leader in both schedules, headed by the group's first slot (counted from
+// first_slot) and length
+pub fn write_diff(
+    out : &mut impl Write,
+    before_label : &str,
+    after_label : &str,
+    first_slot : u64,
+    before : &[Pubkey],
+    after : &[Pubkey]
+) -> std::io::Result<()>
+{
+    writeln!(out, "--- {}", before_label)?;
+    writeln!(out, "+++ {}", after_label)?;
+    let len = before.len().min(after.len());
+    let mut start = 0;
+    while start < len {
+        if before[start] == after[start] {
+            start += 1;
+            continue;
+        }
+        let mut end = start + 1;
+        while (end < len) && (before[end] == before[start]) && (after[end] == after[start]) {
+            end += 1;
+        }
+        let hunk_slot = first_slot + (start as u64);
+        writeln!(out, "@@ -{},{} +{},{} @@", hunk_slot, end - start, hunk_slot, end - start)?;
+        writeln!(out, "-{}", before[start])?;
+        writeln!(out, "+{}", after[start])?;
+        start = end;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn leader_runs_group_consecutive_slots_within_range()
+    {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let leaders = [a, a, b, b, b, a];
+
+        assert_eq!(leader_runs(&leaders, 0..6).collect::<Vec<_>>(), vec![0..2, 2..5, 5..6]);
+        // A range that starts or ends within a run cuts it short
+        assert_eq!(leader_runs(&leaders, 1..4).collect::<Vec<_>>(), vec![1..2, 2..4]);
+        assert_eq!(leader_runs(&leaders, 3..3).count(), 0);
+    }
+
+    #[test]
+    fn write_csv_writes_one_row_per_run()
+    {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let leaders = [a, a, b, b];
+
+        let mut out = vec![];
+        write_csv_header(&mut out).unwrap();
+        write_csv(&mut out, 7, 1000, &leaders, &[0..1, 1..4]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "epoch,first_slot,last_slot,first_slot_index,last_slot_index,leader\n\
+                 7,1000,1000,0,0,{}\n\
+                 7,1001,1001,1,1,{}\n\
+                 7,1002,1003,2,3,{}\n",
+                a, a, b
+            )
+        );
+    }
+
+    #[test]
+    fn write_diff_writes_one_hunk_per_changed_run()
+    {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let c = Pubkey::new_unique();
+        let before = [a, a, a, b, b, c];
+        let after = [a, c, c, b, a, a];
+
+        let mut out = vec![];
+        write_diff(&mut out, "before", "after", 100, &before, &after).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "--- before\n+++ after\n\
+                 @@ -101,2 +101,2 @@\n-{}\n+{}\n\
+                 @@ -104,1 +104,1 @@\n-{}\n+{}\n\
+                 @@ -105,1 +105,1 @@\n-{}\n+{}\n",
+                a, c, b, a, c, a
+            )
+        );
+
+        // Identical schedules have headers only
+        let mut out = vec![];
+        write_diff(&mut out, "before", "after", 100, &before, &before).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "--- before\n+++ after\n");
+    }
+}
@@ -0,0 +1,146 @@
+// Parses the human-friendly forms of the slots and durations that arguments accept
+use std::time::Duration;
+
+// A slot given either absolutely (245000000 or slot:245000000) or as an index within an epoch (epoch:700+1234, or
+// epoch:700 for its first slot).  An epoch-relative slot can only be resolved once the epoch's first slot is known.
+#[derive(Clone, Copy)]
+pub enum SlotArg
+{
+    Slot(u64),
+    EpochIndex
+    {
+        epoch : u64,
+        index : u64
+    }
+}
+
+impl SlotArg
+{
+    // The absolute slot, given the first slot of epoch; None for a slot relative to any other epoch
+    pub fn resolve(
+        &self,
+        epoch : u64,
+        first_slot : u64
+    ) -> Option<u64>
+    {
+        match *self {
+            SlotArg::Slot(slot) => Some(slot),
+            SlotArg::EpochIndex { epoch : slot_epoch, index } if slot_epoch == epoch => first_slot.checked_add(index),
+            SlotArg::EpochIndex { .. } => None
+        }
+    }
+}
+
+impl std::fmt::Display for SlotArg
+{
+    fn fmt(
+        &self,
+        f : &mut std::fmt::Formatter
+    ) -> std::fmt::Result
+    {
+        match self {
+            SlotArg::Slot(slot) => write!(f, "{}", slot),
+            SlotArg::EpochIndex { epoch, index } => write!(f, "epoch:{}+{}", epoch, index)
+        }
+    }
+}
+
+impl std::str::FromStr for SlotArg
+{
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err>
+    {
+        let invalid = || format!("ERROR: Invalid slot {}: expected a slot number, slot:N, or epoch:N+INDEX", s);
+
+        if let Some(epoch_index) = s.strip_prefix("epoch:") {
+            let (epoch, index) = epoch_index.split_once('+').unwrap_or((epoch_index, "0"));
+            return Ok(SlotArg::EpochIndex {
+                epoch : epoch.parse::<u64>().map_err(|_| invalid())?,
+                index : index.parse::<u64>().map_err(|_| invalid())?
+            });
+        }
+
+        s.strip_prefix("slot:").unwrap_or(s).parse::<u64>().map(SlotArg::Slot).map_err(|_| invalid())
+    }
+}
+
+// A duration of one or more numbers each followed by a unit of d, h, m, s or ms, as in 90m or 2h30m.  A bare number is
+// taken as seconds, which is what duration arguments accepted before units were.
+pub fn parse_duration(s : &str) -> Result<Duration, String>
+{
+    let invalid = || format!("ERROR: Invalid duration {}: expected seconds, or a duration such as 90s or 2h30m", s);
+
+    if s.is_empty() {
+        return Err(invalid());
+    }
+
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c : char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let number = rest[..digits].parse::<u64>().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let unit_len = rest.find(|c : char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit_ms = match &rest[..unit_len] {
+            "d" => 86_400_000,
+            "h" => 3_600_000,
+            "m" => 60_000,
+            "s" => 1000,
+            "ms" => 1,
+            _ => return Err(invalid())
+        };
+        total += Duration::from_millis(number.checked_mul(unit_ms).ok_or_else(invalid)?);
+        rest = &rest[unit_len..];
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse_duration_accepts_seconds_and_units()
+    {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h30m"), Ok(Duration::from_secs((2 * 3600) + (30 * 60))));
+        assert_eq!(parse_duration("1500ms"), Ok(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid_durations()
+    {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("1.5h").is_err());
+        assert!(parse_duration(&format!("{}d", u64::MAX / 1000)).is_err());
+    }
+
+    #[test]
+    fn slot_arg_parses_each_form()
+    {
+        assert!(matches!("245000000".parse::<SlotArg>(), Ok(SlotArg::Slot(245000000))));
+        assert!(matches!("slot:245000000".parse::<SlotArg>(), Ok(SlotArg::Slot(245000000))));
+        assert!(matches!("epoch:700+1234".parse::<SlotArg>(), Ok(SlotArg::EpochIndex { epoch : 700, index : 1234 })));
+        assert!(matches!("epoch:700".parse::<SlotArg>(), Ok(SlotArg::EpochIndex { epoch : 700, index : 0 })));
+        assert!("epoch:700+".parse::<SlotArg>().is_err());
+        assert!("slot:".parse::<SlotArg>().is_err());
+    }
+
+    #[test]
+    fn slot_arg_resolves_within_its_epoch_only()
+    {
+        assert_eq!(SlotArg::Slot(5).resolve(700, 1000), Some(5));
+        assert_eq!(SlotArg::EpochIndex { epoch : 700, index : 1234 }.resolve(700, 1000), Some(2234));
+        assert_eq!(SlotArg::EpochIndex { epoch : 700, index : 1234 }.resolve(701, 1000), None);
+        assert_eq!(SlotArg::EpochIndex { epoch : 700, index : 1 }.resolve(700, u64::MAX), None);
+    }
+}
@@ -0,0 +1,186 @@
+// Sends JSON-RPC requests as HTTP/1.1 over a Unix domain socket, for RPC nodes that expose their RPC port only as a
+// local socket.  Each request uses its own connection, which the server closes after responding.
+use solana_client::client_error::{ClientError, Result as ClientResult};
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+pub struct UnixSender
+{
+    url : String,
+
+    path : String,
+
+    timeout : Duration,
+
+    request_id : AtomicU64
+}
+
+impl UnixSender
+{
+    // url is of the form unix:///path/to/rpc.sock.  timeout bounds each whole request, from connect to response.
+    pub fn new(
+        url : String,
+        timeout : Duration
+    ) -> Self
+    {
+        let path = url.trim_start_matches("unix://").to_string();
+        UnixSender { url, path, timeout, request_id : AtomicU64::new(0) }
+    }
+}
+
+#[async_trait::async_trait]
+impl RpcSender for UnixSender
+{
+    async fn send(
+        &self,
+        request : RpcRequest,
+        params : serde_json::Value
+    ) -> ClientResult<serde_json::Value>
+    {
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let body = request.build_request_json(request_id, params).to_string();
+
+        let exchange = async {
+            let mut stream = UnixStream::connect(&self.path).await?;
+            stream
+                .write_all(
+                    format!(
+                        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: \
+                         {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .as_bytes()
+                )
+                .await?;
+
+            let mut response = vec![];
+            stream.read_to_end(&mut response).await?;
+            Ok::<_, std::io::Error>(response)
+        };
+
+        let response = tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out"))??;
+
+        let mut json = serde_json::from_slice::<serde_json::Value>(&http_body(&response)?)?;
+
+        if let Some(error) = json.get("error") {
+            return Err(RpcError::RpcResponseError {
+                code : error["code"].as_i64().unwrap_or(0),
+                message : error["message"].as_str().unwrap_or("").to_string(),
+                data : RpcResponseErrorData::Empty
+            }
+            .into());
+        }
+
+        Ok(json["result"].take())
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats
+    {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String
+    {
+        self.url.clone()
+    }
+}
+
+// Checks the status of an HTTP response and extracts its body, which is either chunked or delimited by the close of
+// the connection.  Errors are the ClientError that send returns, however large.
+#[allow(clippy::result_large_err)]
+fn http_body(response : &[u8]) -> ClientResult<Vec<u8>>
+{
+    let invalid = |reason : &str| ClientError::from(RpcError::ParseError(format!("Invalid HTTP response: {}", reason)));
+
+    let header_end =
+        response.windows(4).position(|window| window == b"\r\n\r\n").ok_or_else(|| invalid("no end of headers"))?;
+    let headers = String::from_utf8_lossy(&response[..header_end]).to_lowercase();
+    let body = &response[(header_end + 4)..];
+
+    let status = headers.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok()) {
+        Some(200) => (),
+        // Reported as I/O errors so that they are retried, as the same statuses are over HTTP
+        Some(code) if (code == 429) || (500..600).contains(&code) => {
+            return Err(ClientError::from(std::io::Error::other(format!("Server responded {}", status))));
+        },
+        _ => return Err(invalid(status))
+    }
+
+    if !headers.lines().any(|line| line.starts_with("transfer-encoding:") && line.contains("chunked")) {
+        return Ok(body.to_vec());
+    }
+
+    // Each chunk is its length in hex, optional extensions, CRLF, the data, CRLF; a zero-length chunk ends the body
+    let mut decoded = vec![];
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|window| window == b"\r\n").ok_or_else(|| invalid("bad chunk"))?;
+        let size = String::from_utf8_lossy(&rest[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(|_| invalid("bad chunk size"))?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let data = rest.get((line_end + 2)..(line_end + 2 + size)).ok_or_else(|| invalid("truncated chunk"))?;
+        decoded.extend_from_slice(data);
+        rest = rest.get((line_end + 4 + size)..).ok_or_else(|| invalid("truncated chunk"))?;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use solana_client::client_error::ClientErrorKind;
+
+    #[test]
+    fn http_body_reads_a_body_delimited_by_close()
+    {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"result\":1}";
+
+        assert_eq!(http_body(response).unwrap(), b"{\"result\":1}");
+    }
+
+    #[test]
+    fn http_body_decodes_chunks()
+    {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         4\r\n{\"re\r\na;ext=1\r\nsult\":1234\r\n1\r\n}\r\n0\r\n\r\n";
+
+        assert_eq!(http_body(response).unwrap(), b"{\"result\":1234}");
+
+        let truncated = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n10\r\n{\"result\":1}";
+        assert!(matches!(http_body(truncated).unwrap_err().kind(), ClientErrorKind::RpcError(RpcError::ParseError(_))));
+
+        let bad_size = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n{}\r\n0\r\n\r\n";
+        assert!(matches!(http_body(bad_size).unwrap_err().kind(), ClientErrorKind::RpcError(RpcError::ParseError(_))));
+    }
+
+    #[test]
+    fn http_body_maps_statuses_to_errors()
+    {
+        // Rate limiting and server errors are I/O errors, so that they are retried
+        for status in ["429 Too Many Requests", "500 Internal Server Error", "503 Service Unavailable"] {
+            let response = format!("HTTP/1.1 {}\r\n\r\n", status);
+            match http_body(response.as_bytes()).unwrap_err().kind() {
+                ClientErrorKind::Io(e) => assert!(e.to_string().starts_with("Server responded")),
+                kind => panic!("{} gave {:?}", status, kind)
+            }
+        }
+
+        for response in ["HTTP/1.1 404 Not Found\r\n\r\n", "HTTP/1.1 200 OK\r\n", "garbage\r\n\r\n"] {
+            assert!(matches!(
+                http_body(response.as_bytes()).unwrap_err().kind(),
+                ClientErrorKind::RpcError(RpcError::ParseError(_))
+            ));
+        }
+    }
+}